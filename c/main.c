@@ -1,6 +1,7 @@
 
 #include "pmsis.h"
 #include <bsp/bsp.h>
+#include <string.h>
 
 
 #define HOTTING 1
@@ -8,12 +9,20 @@
 #define STACK_SIZE      2048
 #define LEN             (131072)
 #define BUF_LEN             (14336*3)
+#ifndef PIPELINE_DEPTH
+#define PIPELINE_DEPTH  3
+#endif
+#ifndef L1_LAYOUT
+#define L1_LAYOUT       "contiguous"
+#endif
 
 
 PI_L2 char data[LEN];
 PI_L2 char data2[LEN];
 PI_L1 char key[32];
-PI_L1 char iv[12];
+PI_L1 char iv[16];
+#define L1_TEST_LEN     1024
+PI_L1 char l1_data[L1_TEST_LEN];
 PI_L2 int lennn[1];
 PI_L2 struct pi_device ram;
 PI_L2 uint32_t ram_ptr;
@@ -22,10 +31,159 @@ void* cluster_init();
 
 void cluster_close(void* wrapper);
 
-void encrypt(char *data, size_t len, char *key, char *iv, void* wrapper, pi_device_t* ram, int cipher);
+int encrypt(char *data, size_t len, char *key, char *iv, size_t iv_len, void* wrapper, pi_device_t* ram, int cipher);
+
+int encrypt_l1(char *data, size_t len, char *key, char *iv, size_t iv_len, void* wrapper, int cipher);
+
+int encrypt_into(const char *source, char *dest, size_t len, char *key, char *iv, size_t iv_len, void* wrapper, pi_device_t* source_ram, pi_device_t* dest_ram, int cipher);
+
+void* stream_init(char *key, char *iv, size_t iv_len, void* wrapper, int cipher);
+
+void* stream_init_segmented(char *key, char *iv, size_t iv_len, void* wrapper, int cipher);
+
+int stream_update(void* session, char *data, size_t len, pi_device_t* ram);
+
+void stream_seek(void* session, uint64_t offset);
+
+uint64_t stream_finish(void* session);
+
+int encrypt_start(char *data, size_t len, char *key, char *iv, size_t iv_len, void* wrapper, pi_device_t* ram, int cipher, void** job);
+
+int encrypt_wait(void* job);
+
+void* context_init(const uint8_t *key, int cipher);
+
+int encrypt_context(char *data, size_t len, const void* context, const uint8_t *iv, size_t iv_len, void* wrapper, pi_device_t* ram);
+
+void context_free(void* context);
+
+int encrypt_blocks(char *data, size_t len, const uint8_t *key, const uint8_t *iv, size_t iv_len, void* wrapper, pi_device_t* ram, int cipher);
+
+int decrypt_blocks(char *data, size_t len, const uint8_t *key, const uint8_t *iv, size_t iv_len, void* wrapper, pi_device_t* ram, int cipher);
+
+int encrypt_xts(char *data, size_t len, const uint8_t *key, uint64_t first_sector, size_t sector_size, void* wrapper, pi_device_t* ram, int cipher);
+
+int decrypt_xts(char *data, size_t len, const uint8_t *key, uint64_t first_sector, size_t sector_size, void* wrapper, pi_device_t* ram, int cipher);
+
+int compute_mac(const char *data, size_t len, const uint8_t *key, uint8_t *tag, void* wrapper, pi_device_t* ram, int cipher);
+
+int check_dma_misuse(const char *data, size_t len, void* wrapper, pi_device_t* ram, uint32_t *failed);
+
+#ifdef PERF_COUNTERS
+struct perf_counters {
+  uint32_t cycles;
+  uint32_t instructions;
+  uint32_t tcdm_contention;
+  uint32_t load_stalls;
+};
+void cluster_perf_counters(const void* wrapper, struct perf_counters* counters);
+
+#define CLUSTER_STATS_VARS() \
+    unsigned long _cl_cycles = 0; \
+    unsigned long _cl_tcdm = 0; \
+    unsigned long _cl_ldstall = 0;
+
+// Sum the counters of all cluster cores during the last operation of [wrapper]
+#define CLUSTER_STATS(wrapper) { \
+      struct perf_counters _cl[NUM_CORES]; \
+      cluster_perf_counters(wrapper, _cl); \
+      for (int _c = 0; _c < NUM_CORES; _c++) { \
+        _cl_cycles += _cl[_c].cycles; \
+        _cl_tcdm   += _cl[_c].tcdm_contention; \
+        _cl_ldstall += _cl[_c].load_stalls; \
+      } \
+    }
+#define PRINT_CLUSTER_STATS() \
+    printf("[cluster] core cycles = %lu\n", _cl_cycles/REPEAT); \
+    printf("[cluster] LD stalls = %lu\n", _cl_ldstall/REPEAT); \
+    printf("[cluster] TCDM contention = %lu\n", _cl_tcdm/REPEAT);
+#else
+#define CLUSTER_STATS_VARS()
+#define CLUSTER_STATS(wrapper)
+#define PRINT_CLUSTER_STATS()
+#endif
+
+struct batch_job {
+  char *data;
+  size_t len;
+  char *key;
+  char *iv;
+  size_t iv_len;
+  pi_device_t* ram;
+  int cipher;
+};
+
+void encrypt_batch(const struct batch_job *jobs, size_t len, int *status, void* wrapper);
 
 void encrypt_serial_orig(char *data, size_t len, char *key, char *iv);
 
+int encrypt_aead(char *data, size_t len, const uint8_t *aad, size_t aad_len, const uint8_t *key, const uint8_t *iv, size_t iv_len, uint8_t *tag, void* wrapper, pi_device_t* ram, int cipher);
+
+int decrypt_aead(char *data, size_t len, const uint8_t *aad, size_t aad_len, const uint8_t *key, const uint8_t *iv, size_t iv_len, const uint8_t *tag, void* wrapper, pi_device_t* ram, int cipher);
+
+#define CIPHER_CHACHA20_PULP 0
+#define CIPHER_CHACHA20   1
+#define CIPHER_AES128_CTR 2
+#define CIPHER_AES128_GCM 3
+#define CIPHER_AES256_GCM 4
+#define CIPHER_XCHACHA20  5
+#define CIPHER_SALSA20    6
+#define CIPHER_XSALSA20   7
+#define CIPHER_CHACHA12_PULP 8
+#define CIPHER_CHACHA8_PULP  9
+#define CIPHER_AES192_CTR       10
+#define CIPHER_AES256_CTR       11
+#define CIPHER_AES128_CTR64BE   12
+#define CIPHER_AES192_CTR64BE   13
+#define CIPHER_AES256_CTR64BE   14
+#define CIPHER_AES128_CTR128BE  15
+#define CIPHER_AES192_CTR128BE  16
+#define CIPHER_AES256_CTR128BE  17
+#define CIPHER_AES128_CBC       18
+#define CIPHER_AES192_CBC       19
+#define CIPHER_AES256_CBC       20
+#define CIPHER_AES128_ECB       21
+#define CIPHER_AES192_ECB       22
+#define CIPHER_AES256_ECB       23
+#define CIPHER_AES128_XTS       24
+#define CIPHER_AES256_XTS       25
+#define CIPHER_POLY1305         26
+#define CIPHER_AES128_PMAC      27
+#define CIPHER_AES256_PMAC      28
+#define CIPHER_AES128_CMAC      29
+#define CIPHER_AES256_CMAC      30
+#define STATUS_OK                   0
+#define STATUS_TAG_MISMATCH         1
+#define STATUS_UNSUPPORTED_CIPHER   2
+#define STATUS_UNSUPPORTED_LOCATION 3
+#define STATUS_KEYSTREAM_OVERFLOW   4
+#define STATUS_INVALID_LENGTH       5
+#define STATUS_ALLOC_ERROR          6
+#define STATUS_DMA_ERROR            7
+#define STATUS_INVALID_STATE        8
+#define STATUS_INVALID_IV_LENGTH    9
+
+// Iv size expected for each cipher
+static size_t cipher_iv_len(int cipher)
+{
+  switch (cipher) {
+  case CIPHER_XCHACHA20:
+  case CIPHER_XSALSA20:
+    return 24;
+  case CIPHER_SALSA20:
+    return 8;
+  case CIPHER_CHACHA20_PULP:
+  case CIPHER_CHACHA20:
+  case CIPHER_AES128_GCM:
+  case CIPHER_AES256_GCM:
+  case CIPHER_CHACHA12_PULP:
+  case CIPHER_CHACHA8_PULP:
+    return 12;
+  default:
+    return 16;
+  }
+}
+
 void test(uint8_t* a,  uint8_t* b, uint8_t* c, uint32_t len);
 
 #define INIT_STATS()  
@@ -39,7 +197,8 @@ void test(uint8_t* a,  uint8_t* b, uint8_t* c, uint32_t len);
     unsigned long _ld = 0; \
     unsigned long _st = 0; \
     unsigned long _ldstall = 0; \
-    unsigned long _imiss = 0; \
+    unsigned long _tcdm = 0; \
+    CLUSTER_STATS_VARS(); \
     for(int _k=0; _k<HOTTING+REPEAT; _k++) { \
       pi_perf_conf((1<<PI_PERF_CYCLES) | (1<<PI_PERF_INSTR) | (1<<PI_PERF_ACTIVE_CYCLES) | (1<<PI_PERF_LD_EXT_CYC) | (1<<PI_PERF_ST_EXT_CYC) | (1<<PI_PERF_JR_STALL) | (1<<PI_PERF_TCDM_CONT) );
 
@@ -58,7 +217,7 @@ void test(uint8_t* a,  uint8_t* b, uint8_t* c, uint32_t len);
         _ld    += pi_perf_read (PI_PERF_LD_EXT_CYC); \
         _st    += pi_perf_read (PI_PERF_ST_EXT_CYC); \
     	_ldstall  += pi_perf_read (PI_PERF_JR_STALL); \
-        _imiss    += pi_perf_read (PI_PERF_TCDM_CONT); \
+        _tcdm     += pi_perf_read (PI_PERF_TCDM_CONT); \
       }
 
 #define EXIT_STATS_LOOP()  \
@@ -69,8 +228,924 @@ void test(uint8_t* a,  uint8_t* b, uint8_t* c, uint32_t len);
     printf("[%d] loads = %lu\n", 0, _ld/REPEAT); \
     printf("[%d] stores = %lu\n", 0, _st/REPEAT); \
     printf("[%d] LD stalls = %lu\fn", 0, _ldstall/REPEAT); \
-    printf("[%d] I$ misses = %lu\n", 0, _imiss/REPEAT);
+    printf("[%d] TCDM contention = %lu\n", 0, _tcdm/REPEAT); \
+    PRINT_CLUSTER_STATS();
+
+// Test cases 2, 3, 4 and 16 from the GCM specification (McGrew & Viega)
+// and the ChaCha20-Poly1305 example of RFC 8439 (2.8.2)
+struct aead_vector {
+  int cipher;
+  const uint8_t *key;
+  const uint8_t *iv;
+  const uint8_t *pt;
+  size_t len;
+  const uint8_t *aad;
+  size_t aad_len;
+  const uint8_t *ct;
+  const uint8_t *tag;
+};
+
+static const uint8_t gcm_zero[16] = {0};
+static const uint8_t gcm_ct2[16] = {
+  0x03, 0x88, 0xda, 0xce, 0x60, 0xb6, 0xa3, 0x92, 0xf3, 0x28, 0xc2, 0xb9, 0x71, 0xb2, 0xfe, 0x78};
+static const uint8_t gcm_tag2[16] = {
+  0xab, 0x6e, 0x47, 0xd4, 0x2c, 0xec, 0x13, 0xbd, 0xf5, 0x3a, 0x67, 0xb2, 0x12, 0x57, 0xbd, 0xdf};
+static const uint8_t gcm_key[32] = {
+  0xfe, 0xff, 0xe9, 0x92, 0x86, 0x65, 0x73, 0x1c, 0x6d, 0x6a, 0x8f, 0x94, 0x67, 0x30, 0x83, 0x08,
+  0xfe, 0xff, 0xe9, 0x92, 0x86, 0x65, 0x73, 0x1c, 0x6d, 0x6a, 0x8f, 0x94, 0x67, 0x30, 0x83, 0x08};
+static const uint8_t gcm_iv[12] = {
+  0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88};
+static const uint8_t gcm_pt[64] = {
+  0xd9, 0x31, 0x32, 0x25, 0xf8, 0x84, 0x06, 0xe5, 0xa5, 0x59, 0x09, 0xc5, 0xaf, 0xf5, 0x26, 0x9a,
+  0x86, 0xa7, 0xa9, 0x53, 0x15, 0x34, 0xf7, 0xda, 0x2e, 0x4c, 0x30, 0x3d, 0x8a, 0x31, 0x8a, 0x72,
+  0x1c, 0x3c, 0x0c, 0x95, 0x95, 0x68, 0x09, 0x53, 0x2f, 0xcf, 0x0e, 0x24, 0x49, 0xa6, 0xb5, 0x25,
+  0xb1, 0x6a, 0xed, 0xf5, 0xaa, 0x0d, 0xe6, 0x57, 0xba, 0x63, 0x7b, 0x39, 0x1a, 0xaf, 0xd2, 0x55};
+static const uint8_t gcm_aad[20] = {
+  0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef, 0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef,
+  0xab, 0xad, 0xda, 0xd2};
+static const uint8_t gcm_ct3[64] = {
+  0x42, 0x83, 0x1e, 0xc2, 0x21, 0x77, 0x74, 0x24, 0x4b, 0x72, 0x21, 0xb7, 0x84, 0xd0, 0xd4, 0x9c,
+  0xe3, 0xaa, 0x21, 0x2f, 0x2c, 0x02, 0xa4, 0xe0, 0x35, 0xc1, 0x7e, 0x23, 0x29, 0xac, 0xa1, 0x2e,
+  0x21, 0xd5, 0x14, 0xb2, 0x54, 0x66, 0x93, 0x1c, 0x7d, 0x8f, 0x6a, 0x5a, 0xac, 0x84, 0xaa, 0x05,
+  0x1b, 0xa3, 0x0b, 0x39, 0x6a, 0x0a, 0xac, 0x97, 0x3d, 0x58, 0xe0, 0x91, 0x47, 0x3f, 0x59, 0x85};
+static const uint8_t gcm_tag3[16] = {
+  0x4d, 0x5c, 0x2a, 0xf3, 0x27, 0xcd, 0x64, 0xa6, 0x2c, 0xf3, 0x5a, 0xbd, 0x2b, 0xa6, 0xfa, 0xb4};
+static const uint8_t gcm_tag4[16] = {
+  0x5b, 0xc9, 0x4f, 0xbc, 0x32, 0x21, 0xa5, 0xdb, 0x94, 0xfa, 0xe9, 0x5a, 0xe7, 0x12, 0x1a, 0x47};
+static const uint8_t gcm_ct16[60] = {
+  0x52, 0x2d, 0xc1, 0xf0, 0x99, 0x56, 0x7d, 0x07, 0xf4, 0x7f, 0x37, 0xa3, 0x2a, 0x84, 0x42, 0x7d,
+  0x64, 0x3a, 0x8c, 0xdc, 0xbf, 0xe5, 0xc0, 0xc9, 0x75, 0x98, 0xa2, 0xbd, 0x25, 0x55, 0xd1, 0xaa,
+  0x8c, 0xb0, 0x8e, 0x48, 0x59, 0x0d, 0xbb, 0x3d, 0xa7, 0xb0, 0x8b, 0x10, 0x56, 0x82, 0x88, 0x38,
+  0xc5, 0xf6, 0x1e, 0x63, 0x93, 0xba, 0x7a, 0x0a, 0xbc, 0xc9, 0xf6, 0x62};
+static const uint8_t gcm_tag16[16] = {
+  0x76, 0xfc, 0x6e, 0xce, 0x0f, 0x4e, 0x17, 0x68, 0xcd, 0xdf, 0x88, 0x53, 0xbb, 0x2d, 0x55, 0x1b};
+
+static const uint8_t chapoly_key[32] = {
+  0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f,
+  0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9c, 0x9d, 0x9e, 0x9f};
+static const uint8_t chapoly_iv[12] = {
+  0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47};
+static const uint8_t chapoly_aad[12] = {
+  0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7};
+static const uint8_t chapoly_pt[114] =
+  "Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+static const uint8_t chapoly_ct[114] = {
+  0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef, 0x7e, 0xc2,
+  0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe, 0xa9, 0xe2, 0xb5, 0xa7, 0x36, 0xee, 0x62, 0xd6,
+  0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12, 0x82, 0xfa, 0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b,
+  0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29, 0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36,
+  0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77, 0x8b, 0x8c, 0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58,
+  0xfa, 0xb3, 0x24, 0xe4, 0xfa, 0xd6, 0x75, 0x94, 0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc,
+  0x3f, 0xf4, 0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d, 0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
+  0x61, 0x16};
+static const uint8_t chapoly_tag[16] = {
+  0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60, 0x06, 0x91};
+
+static const struct aead_vector aead_vectors[] = {
+  {CIPHER_AES128_GCM, gcm_zero, gcm_zero, gcm_zero, 16, NULL, 0, gcm_ct2, gcm_tag2},
+  {CIPHER_AES128_GCM, gcm_key, gcm_iv, gcm_pt, 64, NULL, 0, gcm_ct3, gcm_tag3},
+  {CIPHER_AES128_GCM, gcm_key, gcm_iv, gcm_pt, 60, gcm_aad, 20, gcm_ct3, gcm_tag4},
+  {CIPHER_AES256_GCM, gcm_key, gcm_iv, gcm_pt, 60, gcm_aad, 20, gcm_ct16, gcm_tag16},
+  {CIPHER_CHACHA20, chapoly_key, chapoly_iv, chapoly_pt, 114, chapoly_aad, 12, chapoly_ct, chapoly_tag},
+  {CIPHER_CHACHA20_PULP, chapoly_key, chapoly_iv, chapoly_pt, 114, chapoly_aad, 12, chapoly_ct, chapoly_tag},
+};
+
+// Seal and open every vector, then check that a forged tag leaves the ciphertext in place
+int test_aead(void* wrapper)
+{
+  uint8_t tag[16];
+  for (int v = 0; v < sizeof(aead_vectors) / sizeof(aead_vectors[0]); v++) {
+    const struct aead_vector *t = &aead_vectors[v];
+    memcpy(data, t->pt, t->len);
+    encrypt_aead(data, t->len, t->aad, t->aad_len, t->key, t->iv, 12, tag, wrapper, NULL, t->cipher);
+    if (memcmp(data, t->ct, t->len) || memcmp(tag, t->tag, 16)) {
+      printf("aead vector %d: seal failed\n", v);
+      return -1;
+    }
+    if (decrypt_aead(data, t->len, t->aad, t->aad_len, t->key, t->iv, 12, t->tag, wrapper, NULL, t->cipher) != STATUS_OK
+        || memcmp(data, t->pt, t->len)) {
+      printf("aead vector %d: open failed\n", v);
+      return -1;
+    }
+    memcpy(data, t->ct, t->len);
+    memcpy(tag, t->tag, 16);
+    tag[0] ^= 1;
+    if (decrypt_aead(data, t->len, t->aad, t->aad_len, t->key, t->iv, 12, tag, wrapper, NULL, t->cipher) != STATUS_TAG_MISMATCH
+        || memcmp(data, t->ct, t->len)) {
+      printf("aead vector %d: forged tag accepted\n", v);
+      return -1;
+    }
+  }
+  return 0;
+}
+
+// F.5.1 and F.5.5 from NIST SP 800-38A (first two blocks), AES-CTR with a 128 bit big-endian counter
+static const uint8_t ctr_iv[16] = {
+  0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff};
+static const uint8_t ctr_pt[32] = {
+  0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+  0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51};
+static const uint8_t ctr_key128[16] = {
+  0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c};
+static const uint8_t ctr_ct128[32] = {
+  0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6, 0xce,
+  0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff, 0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff, 0xfd, 0xff};
+static const uint8_t ctr_key256[32] = {
+  0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d, 0x77, 0x81,
+  0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3, 0x09, 0x14, 0xdf, 0xf4};
+static const uint8_t ctr_ct256[32] = {
+  0x60, 0x1e, 0xc3, 0x13, 0x77, 0x57, 0x89, 0xa5, 0xb7, 0xa7, 0xf5, 0x04, 0xbb, 0xf3, 0xd2, 0x28,
+  0xf4, 0x43, 0xe3, 0xca, 0x4d, 0x62, 0xb5, 0x9a, 0xca, 0x84, 0xe9, 0x90, 0xca, 0xca, 0xf5, 0xc5};
+
+int test_ctr(void* wrapper)
+{
+  static const struct { int cipher; const uint8_t *key; const uint8_t *ct; } ctr_vectors[] = {
+    {CIPHER_AES128_CTR128BE, ctr_key128, ctr_ct128},
+    {CIPHER_AES256_CTR128BE, ctr_key256, ctr_ct256},
+  };
+  for (int v = 0; v < sizeof(ctr_vectors) / sizeof(ctr_vectors[0]); v++) {
+    memcpy(data, ctr_pt, 32);
+    if (encrypt(data, 32, (char *)ctr_vectors[v].key, (char *)ctr_iv, 16, wrapper, NULL, ctr_vectors[v].cipher) != STATUS_OK
+        || memcmp(data, ctr_vectors[v].ct, 32)) {
+      printf("ctr vector %d: mismatch\n", v);
+      return -1;
+    }
+    // same vector with the key expanded once, used twice to decrypt back
+    void* context = context_init(ctr_vectors[v].key, ctr_vectors[v].cipher);
+    memcpy(data, ctr_pt, 32);
+    if (context == NULL
+        || encrypt_context(data, 32, context, ctr_iv, 16, wrapper, NULL) != STATUS_OK
+        || memcmp(data, ctr_vectors[v].ct, 32)
+        || encrypt_context(data, 32, context, ctr_iv, 16, wrapper, NULL) != STATUS_OK
+        || memcmp(data, ctr_pt, 32)) {
+      printf("ctr vector %d: mismatch with a cached context\n", v);
+      return -1;
+    }
+    if (encrypt_context(data, 32, context, ctr_iv, 12, wrapper, NULL) != STATUS_INVALID_IV_LENGTH) {
+      printf("ctr vector %d: wrong iv length accepted with a cached context\n", v);
+      return -1;
+    }
+    context_free(context);
+  }
+  // only AES-CTR has a key schedule to cache
+  if (context_init(ctr_key256, CIPHER_CHACHA20) != NULL) {
+    printf("ctr: context for a cipher without key schedule\n");
+    return -1;
+  }
+  return 0;
+}
 
+// F.2.2 and F.1.2 from NIST SP 800-38A (first two blocks), same key and plaintext as the CTR vectors
+static const uint8_t cbc_iv[16] = {
+  0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f};
+static const uint8_t cbc_ct128[32] = {
+  0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19, 0x7d,
+  0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a, 0x91, 0x76, 0x78, 0xb2};
+static const uint8_t ecb_ct128[32] = {
+  0x3a, 0xd7, 0x7b, 0xb4, 0x0d, 0x7a, 0x36, 0x60, 0xa8, 0x9e, 0xca, 0xf3, 0x24, 0x66, 0xef, 0x97,
+  0xf5, 0xd3, 0xd5, 0x85, 0x03, 0xb9, 0x69, 0x9d, 0xe7, 0x85, 0x89, 0x5a, 0x96, 0xfd, 0xba, 0xaf};
+
+// AES-256-CBC decryption over several rounds, checked against the RustCrypto cbc crate with
+// key[i] = i, iv[i] = 7 * i + 1 and data[j] = 31 * j + j / 256 + 7, right after the core and
+// round boundaries of both the 8192 byte (GAP8, PULP-open) and 16384 byte (GAP9) L1 buffers
+#define CBC_LEN 20000
+static const size_t cbc_offsets[] = {0, 1024, 2048, 8192, 16384, 19984};
+static const uint8_t cbc_pt256[6][16] = {
+  {0x87, 0x9f, 0xca, 0x01, 0x7b, 0x16, 0xce, 0x0c, 0xa6, 0xef, 0x2e, 0xce, 0xc4, 0xea, 0x0b, 0x7f},
+  {0x12, 0x25, 0xc9, 0xf3, 0xd0, 0x98, 0x4a, 0xf7, 0x6c, 0x47, 0x9f, 0x93, 0x44, 0x71, 0x4a, 0x5d},
+  {0x95, 0xc4, 0xe9, 0x20, 0x7d, 0x52, 0xfa, 0xdf, 0x9e, 0x1c, 0x69, 0x9f, 0xd3, 0xd1, 0xf2, 0x2a},
+  {0x73, 0x9d, 0xe5, 0x60, 0x2f, 0xfe, 0xc6, 0x86, 0xc5, 0x87, 0x88, 0xbc, 0x50, 0x67, 0x11, 0x53},
+  {0x45, 0x74, 0xf6, 0xc9, 0x97, 0xe2, 0x71, 0x3d, 0x19, 0x65, 0x75, 0x65, 0xde, 0x2a, 0x75, 0x87},
+  {0xc4, 0x1e, 0x68, 0x6d, 0x90, 0x82, 0xe8, 0xfb, 0x0c, 0x9b, 0xd6, 0x12, 0xce, 0x3a, 0xb4, 0x70},
+};
+
+int test_blocks(void* wrapper)
+{
+  memcpy(data, cbc_ct128, 32);
+  if (decrypt_blocks(data, 32, ctr_key128, cbc_iv, 16, wrapper, NULL, CIPHER_AES128_CBC) != STATUS_OK
+      || memcmp(data, ctr_pt, 32)) {
+    printf("blocks: cbc vector mismatch\n");
+    return -1;
+  }
+  memcpy(data, ctr_pt, 32);
+  if (encrypt_blocks(data, 32, ctr_key128, NULL, 0, wrapper, NULL, CIPHER_AES128_ECB) != STATUS_OK
+      || memcmp(data, ecb_ct128, 32)
+      || decrypt_blocks(data, 32, ctr_key128, NULL, 0, wrapper, NULL, CIPHER_AES128_ECB) != STATUS_OK
+      || memcmp(data, ctr_pt, 32)) {
+    printf("blocks: ecb vector mismatch\n");
+    return -1;
+  }
+
+  static uint8_t xkey[32];
+  static uint8_t xiv[16];
+  for (int i = 0; i < 32; i++) {
+    xkey[i] = i;
+  }
+  for (int i = 0; i < 16; i++) {
+    xiv[i] = 7 * i + 1;
+  }
+  for (int j = 0; j < CBC_LEN; j++) {
+    data[j] = data2[j] = j * 31 + j / 256 + 7;
+  }
+  if (decrypt_blocks(data, CBC_LEN, xkey, xiv, 16, wrapper, NULL, CIPHER_AES256_CBC) != STATUS_OK) {
+    printf("blocks: cbc decryption failed\n");
+    return -1;
+  }
+  for (int o = 0; o < sizeof(cbc_offsets) / sizeof(cbc_offsets[0]); o++) {
+    if (memcmp(data + cbc_offsets[o], cbc_pt256[o], 16)) {
+      printf("blocks: cbc mismatch at %d\n", (int)cbc_offsets[o]);
+      return -1;
+    }
+  }
+  // shorter data, also with fewer blocks than cores, must decrypt as the start of the same data
+  memcpy(l1_data, data, L1_TEST_LEN);
+  static const size_t lens[] = {16, 48, 256, L1_TEST_LEN};
+  for (int l = 0; l < sizeof(lens) / sizeof(lens[0]); l++) {
+    size_t len = lens[l];
+    memcpy(data, data2, len);
+    if (decrypt_blocks(data, len, xkey, xiv, 16, wrapper, NULL, CIPHER_AES256_CBC) != STATUS_OK
+        || memcmp(data, l1_data, len)) {
+      printf("blocks: cbc len %d mismatch\n", (int)len);
+      return -1;
+    }
+  }
+  memcpy(data, data2, CBC_LEN);
+  if (encrypt_blocks(data, CBC_LEN, xkey, NULL, 0, wrapper, NULL, CIPHER_AES192_ECB) != STATUS_OK
+      || decrypt_blocks(data, CBC_LEN, xkey, NULL, 0, wrapper, NULL, CIPHER_AES192_ECB) != STATUS_OK
+      || memcmp(data, data2, CBC_LEN)) {
+    printf("blocks: ecb round trip mismatch\n");
+    return -1;
+  }
+
+  // CBC encryption is sequential, and only whole blocks can be processed
+  if (encrypt_blocks(data, 32, xkey, xiv, 16, wrapper, NULL, CIPHER_AES128_CBC) != STATUS_UNSUPPORTED_CIPHER
+      || decrypt_blocks(data, 20, xkey, xiv, 16, wrapper, NULL, CIPHER_AES128_CBC) != STATUS_INVALID_LENGTH
+      || decrypt_blocks(data, 32, xkey, xiv, 16, wrapper, NULL, CIPHER_AES128_ECB) != STATUS_INVALID_IV_LENGTH
+      || encrypt(data, 32, (char *)xkey, (char *)xiv, 16, wrapper, NULL, CIPHER_AES128_CBC) != STATUS_UNSUPPORTED_CIPHER) {
+    printf("blocks: invalid block mode call accepted\n");
+    return -1;
+  }
+  return 0;
+}
+
+// Vectors 1, 2, 15 and 18 from IEEE 1619 (AES-128), data key followed by tweak key
+static const uint8_t xts_key1[32] = {0};
+static const uint8_t xts_key2[32] = {
+  0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+  0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22};
+static const uint8_t xts_key15[32] = {
+  0xff, 0xfe, 0xfd, 0xfc, 0xfb, 0xfa, 0xf9, 0xf8, 0xf7, 0xf6, 0xf5, 0xf4, 0xf3, 0xf2, 0xf1, 0xf0,
+  0xbf, 0xbe, 0xbd, 0xbc, 0xbb, 0xba, 0xb9, 0xb8, 0xb7, 0xb6, 0xb5, 0xb4, 0xb3, 0xb2, 0xb1, 0xb0};
+static const uint8_t xts_pt1[32] = {0};
+static const uint8_t xts_pt2[32] = {
+  0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44,
+  0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44};
+static const uint8_t xts_pt15[20] = {
+  0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+  0x10, 0x11, 0x12, 0x13};
+static const uint8_t xts_ct1[32] = {
+  0x91, 0x7c, 0xf6, 0x9e, 0xbd, 0x68, 0xb2, 0xec, 0x9b, 0x9f, 0xe9, 0xa3, 0xea, 0xdd, 0xa6, 0x92,
+  0xcd, 0x43, 0xd2, 0xf5, 0x95, 0x98, 0xed, 0x85, 0x8c, 0x02, 0xc2, 0x65, 0x2f, 0xbf, 0x92, 0x2e};
+static const uint8_t xts_ct2[32] = {
+  0xc4, 0x54, 0x18, 0x5e, 0x6a, 0x16, 0x93, 0x6e, 0x39, 0x33, 0x40, 0x38, 0xac, 0xef, 0x83, 0x8b,
+  0xfb, 0x18, 0x6f, 0xff, 0x74, 0x80, 0xad, 0xc4, 0x28, 0x93, 0x82, 0xec, 0xd6, 0xd3, 0x94, 0xf0};
+static const uint8_t xts_ct15[17] = {
+  0x6c, 0x16, 0x25, 0xdb, 0x46, 0x71, 0x52, 0x2d, 0x3d, 0x75, 0x99, 0x60, 0x1d, 0xe7, 0xca, 0x09,
+  0xed};
+static const uint8_t xts_ct18[20] = {
+  0x9d, 0x84, 0xc8, 0x13, 0xf7, 0x19, 0xaa, 0x2c, 0x7b, 0xe3, 0xf6, 0x61, 0x71, 0xc7, 0xc5, 0xc2,
+  0xed, 0xbf, 0x9d, 0xac};
+
+// AES-256-XTS over several rounds with 512 byte sectors from sector 5 and a partial final sector,
+// checked against a host implementation validated on the vectors above, with key[i] = i
+// and data[j] = 31 * j + j / 256 + 7, at sector boundaries right after the core and round
+// boundaries of both the 8192 byte and 16384 byte L1 buffers, and at the final sector boundary
+#define XTS_LEN 20008
+static const size_t xts_offsets[] = {0, 1024, 2048, 8192, 16384, 19968, 19992};
+static const uint8_t xts_ct256[7][16] = {
+  {0xb2, 0x4f, 0x8c, 0x3c, 0x35, 0x6a, 0xab, 0x63, 0xfc, 0x86, 0xeb, 0xac, 0xa7, 0xd7, 0xdc, 0x3d},
+  {0x19, 0x70, 0xb9, 0xc7, 0xfa, 0xa1, 0xf3, 0x11, 0xe1, 0xb3, 0xce, 0x26, 0xe5, 0x23, 0x1c, 0x01},
+  {0x54, 0x1c, 0xfc, 0x5a, 0x26, 0x17, 0xfa, 0xa5, 0x18, 0xf6, 0x7d, 0x0f, 0x62, 0x12, 0xb4, 0xaa},
+  {0x02, 0xe8, 0x1a, 0x77, 0xb0, 0xee, 0xf4, 0xf2, 0xf9, 0x6e, 0x9d, 0x09, 0x03, 0x24, 0xe8, 0x5e},
+  {0x46, 0x92, 0x1d, 0x29, 0xd3, 0xc0, 0xe3, 0x11, 0x10, 0x6e, 0x62, 0xe8, 0x2c, 0xf3, 0x38, 0x79},
+  {0xd6, 0x50, 0xf5, 0xce, 0x60, 0xaf, 0x7f, 0x18, 0xd3, 0x57, 0x7e, 0x9f, 0xdb, 0xf1, 0x86, 0x8a},
+  {0xc0, 0x9d, 0xf0, 0x60, 0x28, 0x8d, 0x5d, 0xf8, 0x4d, 0x20, 0xc8, 0x24, 0xb8, 0xe3, 0xf1, 0x48},
+};
+
+int test_xts(void* wrapper)
+{
+  static const struct {
+    const uint8_t *key; uint64_t sector; const uint8_t *pt; const uint8_t *ct; size_t len;
+  } xts_vectors[] = {
+    {xts_key1, 0, xts_pt1, xts_ct1, 32},
+    {xts_key2, 0x3333333333, xts_pt2, xts_ct2, 32},
+    {xts_key15, 0x123456789a, xts_pt15, xts_ct15, 17},
+    {xts_key15, 0x123456789a, xts_pt15, xts_ct18, 20},
+  };
+  for (int v = 0; v < sizeof(xts_vectors) / sizeof(xts_vectors[0]); v++) {
+    size_t len = xts_vectors[v].len;
+    memcpy(data, xts_vectors[v].pt, len);
+    if (encrypt_xts(data, len, xts_vectors[v].key, xts_vectors[v].sector, 512, wrapper, NULL, CIPHER_AES128_XTS) != STATUS_OK
+        || memcmp(data, xts_vectors[v].ct, len)
+        || decrypt_xts(data, len, xts_vectors[v].key, xts_vectors[v].sector, 512, wrapper, NULL, CIPHER_AES128_XTS) != STATUS_OK
+        || memcmp(data, xts_vectors[v].pt, len)) {
+      printf("xts vector %d: mismatch\n", v);
+      return -1;
+    }
+  }
+
+  static uint8_t xkey[64];
+  for (int i = 0; i < 64; i++) {
+    xkey[i] = i;
+  }
+  for (int j = 0; j < XTS_LEN; j++) {
+    data[j] = data2[j] = j * 31 + j / 256 + 7;
+  }
+  if (encrypt_xts(data, XTS_LEN, xkey, 5, 512, wrapper, NULL, CIPHER_AES256_XTS) != STATUS_OK) {
+    printf("xts: encryption failed\n");
+    return -1;
+  }
+  for (int o = 0; o < sizeof(xts_offsets) / sizeof(xts_offsets[0]); o++) {
+    if (memcmp(data + xts_offsets[o], xts_ct256[o], 16)) {
+      printf("xts: mismatch at %d\n", (int)xts_offsets[o]);
+      return -1;
+    }
+  }
+  if (decrypt_xts(data, XTS_LEN, xkey, 5, 512, wrapper, NULL, CIPHER_AES256_XTS) != STATUS_OK
+      || memcmp(data, data2, XTS_LEN)) {
+    printf("xts: round trip mismatch\n");
+    return -1;
+  }
+
+  // sectors must fit in a round and be at least a block long, also the final one
+  if (encrypt_xts(data, 1024, xkey, 0, 8, wrapper, NULL, CIPHER_AES256_XTS) != STATUS_INVALID_LENGTH
+      || encrypt_xts(data, 1024, xkey, 0, 1000, wrapper, NULL, CIPHER_AES256_XTS) != STATUS_INVALID_LENGTH
+      || encrypt_xts(data, 520, xkey, 0, 512, wrapper, NULL, CIPHER_AES256_XTS) != STATUS_INVALID_LENGTH
+      || encrypt_xts(data, 1024, xkey, 0, 512, wrapper, NULL, CIPHER_AES256_CTR) != STATUS_UNSUPPORTED_CIPHER) {
+    printf("xts: invalid sectors accepted\n");
+    return -1;
+  }
+  return 0;
+}
+
+// Poly1305 (RFC 8439 2.5.2), PMAC (reference vectors with key and message 00 01 02 ...)
+// and AES-CMAC (NIST SP 800-38B)
+static const uint8_t poly_key[32] = {
+  0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5, 0x06, 0xa8,
+  0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf, 0x41, 0x49, 0xf5, 0x1b};
+static const uint8_t poly_tag[16] = {
+  0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01, 0x27, 0xa9};
+static const uint8_t pmac_tag3[16] = {
+  0x25, 0x6b, 0xa5, 0x19, 0x3c, 0x1b, 0x99, 0x1b, 0x4d, 0xf0, 0xc5, 0x1f, 0x38, 0x8a, 0x9e, 0x27};
+static const uint8_t pmac_tag34[16] = {
+  0x5c, 0xba, 0x7d, 0x5e, 0xb2, 0x4f, 0x7c, 0x86, 0xcc, 0xc5, 0x46, 0x04, 0xe5, 0x3d, 0x55, 0x12};
+static const uint8_t cmac_key[16] = {
+  0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c};
+static const uint8_t cmac_msg[40] = {
+  0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+  0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+  0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11};
+static const uint8_t cmac_tags[3][16] = {
+  {0xbb, 0x1d, 0x69, 0x29, 0xe9, 0x59, 0x37, 0x28, 0x7f, 0xa3, 0x7d, 0x12, 0x9b, 0x75, 0x67, 0x46},
+  {0x07, 0x0a, 0x16, 0xb4, 0x6b, 0x4d, 0x41, 0x44, 0xf7, 0x9b, 0xdd, 0x9d, 0xd0, 0x4a, 0x28, 0x7c},
+  {0xdf, 0xa6, 0x67, 0x47, 0xde, 0x9a, 0xe6, 0x30, 0x30, 0xca, 0x32, 0x61, 0x14, 0x97, 0xc8, 0x27},
+};
+
+// MACs of 20005 bytes over several rounds with key[i] = i and data[j] = 31 * j + j / 256 + 7,
+// checked against the RustCrypto poly1305 / cmac crates and a host PMAC validated on the vectors above
+#define MAC_LEN 20005
+static const struct {
+  int cipher;
+  uint8_t tag[16];
+} mac_vectors[] = {
+  {CIPHER_POLY1305, {0x7c, 0x98, 0xb8, 0xe0, 0xdd, 0x9a, 0xe6, 0x53, 0xa8, 0x9a, 0x2e, 0x08, 0x60, 0x17, 0x2b, 0x4e}},
+  {CIPHER_AES256_PMAC, {0xec, 0xf5, 0x3a, 0x2e, 0x92, 0x0c, 0x13, 0xb9, 0x45, 0x15, 0x9b, 0x59, 0xc1, 0xaa, 0xdd, 0xd8}},
+  {CIPHER_AES256_CMAC, {0x25, 0x64, 0xc9, 0x05, 0x96, 0xd6, 0xca, 0x6e, 0x14, 0x97, 0xa6, 0x30, 0x2d, 0xf3, 0xa0, 0xb0}},
+};
+
+int test_mac(void* wrapper)
+{
+  static uint8_t tag[16];
+  static uint8_t mkey[32];
+  for (int i = 0; i < 32; i++) {
+    mkey[i] = i;
+  }
+
+  memcpy(data, "Cryptographic Forum Research Group", 34);
+  if (compute_mac(data, 34, poly_key, tag, wrapper, NULL, CIPHER_POLY1305) != STATUS_OK
+      || memcmp(tag, poly_tag, 16)) {
+    printf("poly1305: mismatch\n");
+    return -1;
+  }
+  for (int j = 0; j < 34; j++) {
+    data[j] = j;
+  }
+  if (compute_mac(data, 3, mkey, tag, wrapper, NULL, CIPHER_AES128_PMAC) != STATUS_OK || memcmp(tag, pmac_tag3, 16)
+      || compute_mac(data, 34, mkey, tag, wrapper, NULL, CIPHER_AES128_PMAC) != STATUS_OK || memcmp(tag, pmac_tag34, 16)) {
+    printf("pmac: mismatch\n");
+    return -1;
+  }
+  static const size_t cmac_lens[] = {0, 16, 40};
+  memcpy(data, cmac_msg, 40);
+  for (int v = 0; v < 3; v++) {
+    if (compute_mac(data, cmac_lens[v], cmac_key, tag, wrapper, NULL, CIPHER_AES128_CMAC) != STATUS_OK
+        || memcmp(tag, cmac_tags[v], 16)) {
+      printf("cmac vector %d: mismatch\n", v);
+      return -1;
+    }
+  }
+
+  for (int j = 0; j < MAC_LEN; j++) {
+    data[j] = data2[j] = j * 31 + j / 256 + 7;
+  }
+  for (int v = 0; v < sizeof(mac_vectors) / sizeof(mac_vectors[0]); v++) {
+    if (compute_mac(data, MAC_LEN, mkey, tag, wrapper, NULL, mac_vectors[v].cipher) != STATUS_OK
+        || memcmp(tag, mac_vectors[v].tag, 16)) {
+      printf("mac %d: mismatch\n", mac_vectors[v].cipher);
+      return -1;
+    }
+  }
+  // the data is only read
+  if (memcmp(data, data2, MAC_LEN)) {
+    printf("mac: data modified\n");
+    return -1;
+  }
+  if (compute_mac(data, MAC_LEN, mkey, tag, wrapper, NULL, CIPHER_AES256_CTR) != STATUS_UNSUPPORTED_CIPHER) {
+    printf("mac: stream cipher accepted\n");
+    return -1;
+  }
+  return 0;
+}
+
+// Extended nonce, reduced-round and AES-CTR ciphers, checked against the RustCrypto chacha20 / salsa20 /
+// ctr crates with key[i] = i, iv[i] = 7 * i + 1 and data[j] = 31 * j + 7, at offsets 0, 2048 and 4080
+#define VECTOR_LEN 4096
+struct cipher_vector {
+  int cipher;
+  uint8_t ct[3][16];
+};
+
+static const struct cipher_vector cipher_vectors[] = {
+  {CIPHER_XCHACHA20, {
+    {0xa6,0x7f,0x04,0xab,0xa0,0x81,0x44,0x62,0xe1,0xd5,0xf5,0x15,0x72,0xf9,0xe9,0x1f},
+    {0xfa,0x17,0xc2,0xdb,0x8b,0x2f,0xa3,0x5a,0x16,0x0e,0x96,0x8b,0xf4,0xec,0xd5,0x95},
+    {0x65,0xf1,0x2f,0xc3,0xa7,0x5a,0x99,0xf0,0x81,0x2d,0x93,0x8e,0x6e,0x77,0x9a,0x1e}}},
+  {CIPHER_SALSA20, {
+    {0x6f,0xf4,0xbf,0x2d,0x92,0x15,0x26,0x3a,0xbf,0xdb,0x62,0xa6,0xc9,0x41,0x8c,0x39},
+    {0x56,0xfa,0x84,0xb6,0x3c,0x83,0x9e,0xf9,0x29,0xdc,0xf2,0x1c,0x68,0xa3,0xcd,0x5b},
+    {0x10,0xc1,0x5f,0x6d,0xac,0x65,0x20,0x81,0xc4,0xcb,0x2d,0x66,0x4d,0x8e,0xc7,0xef}}},
+  {CIPHER_XSALSA20, {
+    {0x6d,0x22,0x4d,0xac,0x5a,0x27,0xef,0x63,0x93,0xc8,0x7b,0x1f,0x56,0x49,0xaa,0x0d},
+    {0x16,0x1d,0x37,0x62,0x76,0x40,0xb8,0x44,0xe9,0xf7,0xe4,0x6c,0xe4,0x2e,0xec,0x65},
+    {0x15,0x5c,0xd4,0x20,0x13,0x1b,0x71,0x7e,0xe8,0x02,0xd1,0x8b,0x97,0xb3,0x2b,0x12}}},
+  {CIPHER_CHACHA12_PULP, {
+    {0x88,0x3d,0x0f,0x16,0x95,0x4e,0x41,0x5c,0xfc,0xb9,0x7b,0x40,0xec,0x25,0x96,0x04},
+    {0x23,0x12,0xe6,0xf7,0x10,0x70,0x25,0x46,0xbe,0xd7,0x7c,0x99,0x09,0xfb,0x80,0xf5},
+    {0xed,0xab,0xec,0x9d,0xd1,0xac,0x87,0xcc,0xa4,0xf2,0xe5,0xaf,0xde,0x04,0x55,0xd7}}},
+  {CIPHER_CHACHA8_PULP, {
+    {0x9d,0x18,0xbf,0xb6,0xb7,0x0e,0x6a,0xca,0x38,0xa7,0xe5,0xf1,0x6b,0xc3,0x32,0x21},
+    {0xf6,0x68,0x6a,0x4f,0xbf,0xc1,0xf0,0xf3,0x59,0x6f,0x2b,0xf4,0xa3,0xc0,0x56,0x9b},
+    {0x88,0xf5,0x9a,0xc3,0x1a,0x49,0x81,0xff,0x88,0xa0,0x71,0xed,0x80,0x85,0xe4,0x8d}}},
+  {CIPHER_AES256_CTR, {
+    {0x92,0xef,0x46,0x69,0xc9,0x68,0x83,0xb9,0x81,0x01,0x24,0xc6,0xee,0x7b,0x1c,0x30},
+    {0x29,0x88,0xa7,0x03,0xc7,0x6e,0x6d,0xd5,0x80,0x1f,0xc6,0xff,0x46,0xa8,0xc0,0xb4},
+    {0xe2,0x73,0xec,0xda,0x3a,0xe7,0xdc,0xf3,0x71,0x67,0x7a,0x56,0x09,0xb9,0xc9,0xb8}}},
+  {CIPHER_AES192_CTR64BE, {
+    {0x49,0x6c,0x5e,0x75,0xf2,0x6b,0x28,0x50,0xe2,0xc4,0x89,0x75,0xe6,0x3e,0xf0,0xee},
+    {0x5d,0x58,0x59,0x71,0xe5,0x61,0xf6,0x61,0xcc,0x4d,0xf7,0x7a,0xfb,0xdd,0x67,0x48},
+    {0x5d,0xcb,0x54,0x89,0xa1,0x20,0x77,0x00,0xd0,0x6a,0x83,0x96,0x20,0x21,0x77,0x41}}},
+  {CIPHER_AES128_CTR128BE, {
+    {0xdd,0x65,0x0f,0xc1,0x3b,0xaa,0x9d,0xa1,0x61,0xa4,0x47,0xee,0xaa,0xd0,0xf0,0xaf},
+    {0x4e,0x6c,0x03,0xf4,0x1d,0x6e,0x49,0x51,0x48,0x21,0xa3,0x9a,0xb8,0xda,0xc5,0xbe},
+    {0xb3,0x39,0xd6,0xa4,0x0b,0x38,0x8e,0x39,0x14,0x6d,0xd9,0xfc,0x98,0x9f,0x60,0x88}}},
+  {CIPHER_AES256_CTR128BE, {
+    {0x92,0xef,0x46,0x69,0xc9,0x68,0x83,0xb9,0x81,0x01,0x24,0xc6,0xee,0x7b,0x1c,0x30},
+    {0xc0,0x72,0xaf,0x13,0xde,0x80,0x95,0xed,0x83,0x3e,0x74,0x64,0x03,0xf2,0x4e,0x23},
+    {0x12,0xda,0x90,0xe8,0x90,0x7d,0x1c,0xd1,0x49,0x8b,0xea,0x3b,0x78,0x08,0xd5,0xcd}}},
+};
+
+// Every vector through a single call, a stream in pieces and an asynchronous job
+int test_vectors(void* wrapper)
+{
+  static const size_t offsets[] = {0, 2048, 4080};
+  static char xkey[32];
+  static char xiv[24];
+  for (int i = 0; i < 32; i++) {
+    xkey[i] = i;
+  }
+  for (int i = 0; i < 24; i++) {
+    xiv[i] = 7 * i + 1;
+  }
+  for (int v = 0; v < sizeof(cipher_vectors) / sizeof(cipher_vectors[0]); v++) {
+    const struct cipher_vector* t = &cipher_vectors[v];
+    for (int j = 0; j < VECTOR_LEN; j++) {
+      data[j] = data2[j] = j * 31 + 7;
+    }
+    if (encrypt(data, VECTOR_LEN, xkey, xiv, cipher_iv_len(t->cipher), wrapper, NULL, t->cipher) != STATUS_OK) {
+      printf("cipher vector %d: failed\n", v);
+      return -1;
+    }
+    for (int o = 0; o < 3; o++) {
+      if (memcmp(data + offsets[o], t->ct[o], 16)) {
+        printf("cipher vector %d: mismatch at %d\n", v, (int)offsets[o]);
+        return -1;
+      }
+    }
+    void* session = stream_init(xkey, xiv, cipher_iv_len(t->cipher), wrapper, t->cipher);
+    stream_update(session, data2, 100, NULL);
+    stream_update(session, data2 + 100, VECTOR_LEN - 100, NULL);
+    if (stream_finish(session) != VECTOR_LEN || memcmp(data, data2, VECTOR_LEN)) {
+      printf("cipher vector %d: stream mismatch\n", v);
+      return -1;
+    }
+    for (int j = 0; j < VECTOR_LEN; j++) {
+      data2[j] = j * 31 + 7;
+    }
+    void* job;
+    if (encrypt_start(data2, VECTOR_LEN, xkey, xiv, cipher_iv_len(t->cipher), wrapper, NULL, t->cipher, &job) != STATUS_OK
+        || encrypt_wait(job) != STATUS_OK || memcmp(data, data2, VECTOR_LEN)) {
+      printf("cipher vector %d: async mismatch\n", v);
+      return -1;
+    }
+  }
+  return 0;
+}
+
+// Data in L1 must be encrypted exactly as the same data in L2
+int test_l1(void* wrapper)
+{
+  static const size_t lens[] = {1, 15, 16, 64, 100, 257, L1_TEST_LEN};
+  for (int cipher = 0; cipher < 3; cipher++) {
+    for (int l = 0; l < sizeof(lens) / sizeof(lens[0]); l++) {
+      size_t len = lens[l];
+      for (int j = 0; j < len; j++) {
+        data[j] = l1_data[j] = j * 31 + 7;
+      }
+      encrypt(data, len, key, iv, cipher_iv_len(cipher), wrapper, NULL, cipher);
+      encrypt_l1(l1_data, len, key, iv, cipher_iv_len(cipher), wrapper, cipher);
+      if (memcmp(data, l1_data, len)) {
+        printf("l1 cipher %d len %d: mismatch with l2\n", cipher, (int)len);
+        return -1;
+      }
+    }
+  }
+  return 0;
+}
+
+// Encrypting a stream in unaligned pieces must give the same result as a single call,
+// also for segmented sessions while still in the first segment (see test_segments for the next ones)
+int test_stream(void* wrapper)
+{
+  static const size_t chunks[] = {1, 63, 64, 1000, 17, 30000, 5};
+  for (int run = 0; run < 6; run++) {
+    int cipher = run % 3;
+    size_t total = 0;
+    for (int c = 0; c < sizeof(chunks) / sizeof(chunks[0]); c++) {
+      total += chunks[c];
+    }
+    for (int j = 0; j < total; j++) {
+      data[j] = data2[j] = j * 31 + 7;
+    }
+    encrypt(data, total, key, iv, cipher_iv_len(cipher), wrapper, NULL, cipher);
+    void* session = run < 3 ? stream_init(key, iv, cipher_iv_len(cipher), wrapper, cipher)
+                            : stream_init_segmented(key, iv, cipher_iv_len(cipher), wrapper, cipher);
+    size_t offset = 0;
+    for (int c = 0; c < sizeof(chunks) / sizeof(chunks[0]); c++) {
+      stream_update(session, data2 + offset, chunks[c], NULL);
+      offset += chunks[c];
+    }
+    if (stream_finish(session) != total || memcmp(data, data2, total)) {
+      printf("stream run %d: mismatch with single call\n", run);
+      return -1;
+    }
+  }
+  return 0;
+}
+
+// Segmented sessions crossing the end of the keystream of the iv, 100 bytes before it, with
+// key[i] = i, iv[i] = 7 * i + 1 and data[j] = 31 * j + 7, checked against the RustCrypto chacha20 /
+// ctr crates continuing with the next nonce at offsets 0, 96 (across the boundary) and 284.
+// ChaCha20 and AES-CTR segments are 2^32 - 1 blocks of 64 and 16 bytes.
+#define SEGMENT_TEST_LEN 300
+static const struct {
+  int cipher;
+  uint64_t segment_len;
+  uint8_t ct[3][16];
+} segment_vectors[] = {
+  {CIPHER_CHACHA20_PULP, 0xffffffffULL * 64, {
+  {0xc6, 0x4d, 0x39, 0x48, 0xb9, 0x1f, 0x00, 0x18, 0xfc, 0x79, 0x41, 0x30, 0x46, 0x49, 0x4f, 0xd1},
+  {0x83, 0x66, 0x10, 0xcc, 0x82, 0x9d, 0xf4, 0x87, 0xab, 0xc7, 0xbc, 0x4f, 0xd6, 0xc6, 0xbf, 0x0f},
+  {0x69, 0xfa, 0xa7, 0x06, 0xc4, 0x04, 0xbe, 0x85, 0x3f, 0x0f, 0x3e, 0x7c, 0x8c, 0xe5, 0x72, 0xde}}},
+  {CIPHER_CHACHA20, 0xffffffffULL * 64, {
+  {0xc6, 0x4d, 0x39, 0x48, 0xb9, 0x1f, 0x00, 0x18, 0xfc, 0x79, 0x41, 0x30, 0x46, 0x49, 0x4f, 0xd1},
+  {0x83, 0x66, 0x10, 0xcc, 0x82, 0x9d, 0xf4, 0x87, 0xab, 0xc7, 0xbc, 0x4f, 0xd6, 0xc6, 0xbf, 0x0f},
+  {0x69, 0xfa, 0xa7, 0x06, 0xc4, 0x04, 0xbe, 0x85, 0x3f, 0x0f, 0x3e, 0x7c, 0x8c, 0xe5, 0x72, 0xde}}},
+  {CIPHER_AES128_CTR, 0xffffffffULL * 16, {
+  {0x09, 0xc9, 0x24, 0x9a, 0x18, 0xe6, 0xf5, 0x4f, 0xc1, 0xbd, 0x60, 0x94, 0x7e, 0xce, 0x91, 0x84},
+  {0xe5, 0xa4, 0x6a, 0x0a, 0x39, 0xcc, 0x82, 0x41, 0x5a, 0xbe, 0xa8, 0x1e, 0xfa, 0x84, 0x44, 0x62},
+  {0xf8, 0x55, 0x84, 0x9f, 0x9d, 0xb8, 0x1d, 0x33, 0xd0, 0xc8, 0xa2, 0x51, 0x6c, 0x78, 0xc8, 0x40}}},
+};
+
+int test_segments(void* wrapper)
+{
+  static const size_t offsets[] = {0, 96, 284};
+  static char skey[32];
+  static char siv[16];
+  for (int i = 0; i < 32; i++) {
+    skey[i] = i;
+  }
+  for (int i = 0; i < 16; i++) {
+    siv[i] = 7 * i + 1;
+  }
+  for (int v = 0; v < sizeof(segment_vectors) / sizeof(segment_vectors[0]); v++) {
+    int cipher = segment_vectors[v].cipher;
+    uint64_t start = segment_vectors[v].segment_len - 100;
+    for (int j = 0; j < SEGMENT_TEST_LEN; j++) {
+      data[j] = data2[j] = j * 31 + 7;
+    }
+    // a plain session ends with the keystream of the iv
+    void* session = stream_init(skey, siv, cipher_iv_len(cipher), wrapper, cipher);
+    stream_seek(session, start);
+    if (stream_update(session, data, SEGMENT_TEST_LEN, NULL) != STATUS_KEYSTREAM_OVERFLOW
+        || stream_finish(session) != start || memcmp(data, data2, SEGMENT_TEST_LEN)) {
+      printf("segment vector %d: plain session crossed the end of the keystream\n", v);
+      return -1;
+    }
+    // the boundary is crossed both within a single update and between two of them
+    session = stream_init_segmented(skey, siv, cipher_iv_len(cipher), wrapper, cipher);
+    stream_seek(session, start);
+    if (stream_update(session, data, SEGMENT_TEST_LEN, NULL) != STATUS_OK
+        || stream_finish(session) != start + SEGMENT_TEST_LEN) {
+      printf("segment vector %d: segmented session failed\n", v);
+      return -1;
+    }
+    session = stream_init_segmented(skey, siv, cipher_iv_len(cipher), wrapper, cipher);
+    stream_seek(session, start);
+    if (stream_update(session, data2, 100, NULL) != STATUS_OK
+        || stream_update(session, data2 + 100, SEGMENT_TEST_LEN - 100, NULL) != STATUS_OK
+        || stream_finish(session) != start + SEGMENT_TEST_LEN) {
+      printf("segment vector %d: segmented session failed at the boundary\n", v);
+      return -1;
+    }
+    for (int o = 0; o < sizeof(offsets) / sizeof(offsets[0]); o++) {
+      if (memcmp(data + offsets[o], segment_vectors[v].ct[o], 16)
+          || memcmp(data2 + offsets[o], segment_vectors[v].ct[o], 16)) {
+        printf("segment vector %d: mismatch at %d\n", v, (int)offsets[o]);
+        return -1;
+      }
+    }
+  }
+  return 0;
+}
+
+// Double buffering: refill one buffer while the cluster encrypts the other,
+// results must match one blocking call per packet
+#define ASYNC_PACKETS     6
+#define ASYNC_PACKET_LEN  20000
+static void async_fill(char* buf, int packet)
+{
+  for (int j = 0; j < ASYNC_PACKET_LEN; j++) {
+    buf[j] = j * 13 + packet;
+  }
+}
+
+static int async_check(void* job, int packet)
+{
+  char* buf = data + (packet % 2) * ASYNC_PACKET_LEN;
+  if (encrypt_wait(job) != STATUS_OK || memcmp(buf, data2 + packet * ASYNC_PACKET_LEN, ASYNC_PACKET_LEN)) {
+    printf("async packet %d: mismatch with single call\n", packet);
+    return -1;
+  }
+  return 0;
+}
+
+int test_async(void* wrapper)
+{
+  static char ivs[ASYNC_PACKETS][16];
+  void* jobs[2] = {NULL, NULL};
+  for (int p = 0; p < ASYNC_PACKETS; p++) {
+    for (int j = 0; j < 16; j++) {
+      ivs[p][j] = p + j;
+    }
+    async_fill(data2 + p * ASYNC_PACKET_LEN, p);
+    encrypt(data2 + p * ASYNC_PACKET_LEN, ASYNC_PACKET_LEN, key, ivs[p], cipher_iv_len(p % 3), wrapper, NULL, p % 3);
+  }
+  for (int p = 0; p < ASYNC_PACKETS; p++) {
+    char* buf = data + (p % 2) * ASYNC_PACKET_LEN;
+    // the job two packets back used this buffer
+    if (p >= 2 && async_check(jobs[p % 2], p - 2)) {
+      return -1;
+    }
+    async_fill(buf, p);
+    if (encrypt_start(buf, ASYNC_PACKET_LEN, key, ivs[p], cipher_iv_len(p % 3), wrapper, NULL, p % 3, &jobs[p % 2]) != STATUS_OK) {
+      printf("async packet %d: not started\n", p);
+      return -1;
+    }
+  }
+  // the last two jobs are still in flight, there is no room in L1 for a third one
+  void* third = NULL;
+  char* spare = data + 2 * ASYNC_PACKET_LEN;
+  async_fill(spare, 0);
+  int status = encrypt_start(spare, ASYNC_PACKET_LEN, key, ivs[0], cipher_iv_len(0), wrapper, NULL, 0, &third);
+  if (status != STATUS_INVALID_STATE || third != NULL) {
+    printf("async: third job in flight: %d\n", status);
+    return -1;
+  }
+  for (int j = 0; j < ASYNC_PACKET_LEN; j++) {
+    if (spare[j] != (char)(j * 13)) {
+      printf("async: rejected job modified its data\n");
+      return -1;
+    }
+  }
+  for (int p = ASYNC_PACKETS - 2; p < ASYNC_PACKETS; p++) {
+    if (async_check(jobs[p % 2], p)) {
+      return -1;
+    }
+  }
+  return 0;
+}
+
+// Every message of a batch must be encrypted as if it was encrypted on its own
+#define BATCH_JOBS 24
+int test_batch(void* wrapper)
+{
+  static struct batch_job jobs[BATCH_JOBS];
+  static char keys[BATCH_JOBS][32];
+  static char ivs[BATCH_JOBS][16];
+  static int status[BATCH_JOBS];
+  size_t offset = 0;
+  for (int i = 0; i < BATCH_JOBS; i++) {
+    // mostly small packets, with one bigger than the L1 buffers
+    size_t len = i == 7 ? 30000 : (i * 97) % 1500 + 1;
+    for (int j = 0; j < 32; j++) {
+      keys[i][j] = i + j;
+    }
+    for (int j = 0; j < 16; j++) {
+      ivs[i][j] = i * j;
+    }
+    for (int j = 0; j < len; j++) {
+      data[offset + j] = data2[offset + j] = j * 31 + i;
+    }
+    jobs[i] = (struct batch_job){data + offset, len, keys[i], ivs[i], cipher_iv_len(i % 3), NULL, i % 3};
+    encrypt(data2 + offset, len, keys[i], ivs[i], cipher_iv_len(i % 3), wrapper, NULL, i % 3);
+    offset += len;
+  }
+  encrypt_batch(jobs, BATCH_JOBS, status, wrapper);
+  for (int i = 0; i < BATCH_JOBS; i++) {
+    if (status[i] != 0 || memcmp(jobs[i].data, data2 + (jobs[i].data - data), jobs[i].len)) {
+      printf("batch job %d: mismatch with single call\n", i);
+      return -1;
+    }
+  }
+  return 0;
+}
+
+// Encrypting into another buffer must give the in-place result for every pair of locations,
+// leaving the source untouched. With LAYOUT=interleaved every core queues its own HyperRAM
+// requests, so this also checks that they are not lost when issued concurrently.
+// several rounds of the L1 buffers of every platform
+#define INTO_LEN 20001
+int test_into(void* wrapper)
+{
+  struct pi_hyperram_conf ram_conf;
+  uint32_t src_ram, dst_ram;
+  char *src = data2, *out = data2 + INTO_LEN;
+  pi_hyperram_conf_init(&ram_conf);
+  pi_open_from_conf(&ram, &ram_conf);
+  if (pi_ram_open(&ram) || pi_ram_alloc(&ram, &src_ram, INTO_LEN) || pi_ram_alloc(&ram, &dst_ram, INTO_LEN)) {
+    printf("into: unable to use HyperRAM\n");
+    return -1;
+  }
+  int err = 0;
+  for (int cipher = 0; cipher < 3 && !err; cipher++) {
+    for (int j = 0; j < INTO_LEN; j++) {
+      data[j] = src[j] = j * 31 + 7;
+    }
+    encrypt(data, INTO_LEN, key, iv, cipher_iv_len(cipher), wrapper, NULL, cipher);
+    pi_ram_write(&ram, src_ram, src, INTO_LEN);
+
+    // L2 -> RAM
+    memset(out, 0, INTO_LEN);
+    pi_ram_write(&ram, dst_ram, out, INTO_LEN);
+    int status = encrypt_into(src, (char *)dst_ram, INTO_LEN, key, iv, cipher_iv_len(cipher), wrapper, NULL, &ram, cipher);
+    pi_ram_read(&ram, dst_ram, out, INTO_LEN);
+    if (status != STATUS_OK || memcmp(data, out, INTO_LEN)) {
+      printf("into cipher %d: l2 -> ram mismatch with in-place\n", cipher);
+      err = -1;
+      break;
+    }
+    for (int j = 0; j < INTO_LEN; j++) {
+      if (src[j] != (char)(j * 31 + 7)) {
+        printf("into cipher %d: l2 source modified\n", cipher);
+        err = -1;
+        break;
+      }
+    }
+
+    // RAM -> L2
+    memset(out, 0, INTO_LEN);
+    status = encrypt_into((char *)src_ram, out, INTO_LEN, key, iv, cipher_iv_len(cipher), wrapper, &ram, NULL, cipher);
+    if (status != STATUS_OK || memcmp(data, out, INTO_LEN)) {
+      printf("into cipher %d: ram -> l2 mismatch with in-place\n", cipher);
+      err = -1;
+      break;
+    }
+
+    // RAM -> RAM
+    memset(out, 0, INTO_LEN);
+    pi_ram_write(&ram, dst_ram, out, INTO_LEN);
+    status = encrypt_into((char *)src_ram, (char *)dst_ram, INTO_LEN, key, iv, cipher_iv_len(cipher), wrapper, &ram, &ram, cipher);
+    pi_ram_read(&ram, dst_ram, out, INTO_LEN);
+    if (status != STATUS_OK || memcmp(data, out, INTO_LEN)) {
+      printf("into cipher %d: ram -> ram mismatch with in-place\n", cipher);
+      err = -1;
+      break;
+    }
+    pi_ram_read(&ram, src_ram, out, INTO_LEN);
+    if (memcmp(src, out, INTO_LEN)) {
+      printf("into cipher %d: ram source modified\n", cipher);
+      err = -1;
+    }
+  }
+  pi_ram_free(&ram, dst_ram, INTO_LEN);
+  pi_ram_free(&ram, src_ram, INTO_LEN);
+  pi_ram_close(&ram);
+  return err;
+}
+
+// Every misuse of a DMA buffer must be reported: on the FC, from the wrong team size,
+// flushing before the last round, advancing past it, flushing twice, advancing after flushing
+int test_dma_misuse(void* wrapper)
+{
+  // longer than the L1 buffers of every platform, so that it takes more than one round
+  size_t len = 2 * BUF_LEN + 100;
+  uint32_t failed = 0;
+  for (int j = 0; j < len; j++) {
+    data[j] = data2[j] = j * 31 + 7;
+  }
+  int status = check_dma_misuse(data, len, wrapper, NULL, &failed);
+  if (status != STATUS_OK || failed) {
+    printf("dma misuse: status %d, not reported: %x\n", status, (unsigned)failed);
+    return -1;
+  }
+  if (memcmp(data, data2, len)) {
+    printf("dma misuse: source modified\n");
+    return -1;
+  }
+  return 0;
+}
+
+// Failures must be reported without touching the data, and the wrapper must stay usable
+int test_errors(void* wrapper)
+{
+  for (int j = 0; j < 64; j++) {
+    data[j] = data2[j] = j;
+  }
+  if (encrypt(data, 64, key, iv, 12, wrapper, NULL, CIPHER_AES128_GCM) != STATUS_UNSUPPORTED_CIPHER) {
+    printf("errors: gcm accepted as stream cipher\n");
+    return -1;
+  }
+  // ram is not opened yet
+  int status = encrypt(data, 64, key, iv, 12, wrapper, &ram, 0);
+  if ((status != STATUS_DMA_ERROR && status != STATUS_UNSUPPORTED_LOCATION) || memcmp(data, data2, 64)) {
+    printf("errors: transfer from a closed ram device: %d\n", status);
+    return -1;
+  }
+  if (stream_init(key, iv, 12, wrapper, CIPHER_AES256_GCM) != NULL) {
+    printf("errors: gcm accepted for streaming\n");
+    return -1;
+  }
+  // AES-CTR takes a 16 byte iv, not the 12 bytes of ChaCha20
+  status = encrypt(data, 64, key, iv, 12, wrapper, NULL, CIPHER_AES256_CTR128BE);
+  if (status != STATUS_INVALID_IV_LENGTH || memcmp(data, data2, 64)) {
+    printf("errors: wrong iv length accepted: %d\n", status);
+    return -1;
+  }
+  if (stream_init(key, iv, 16, wrapper, CIPHER_CHACHA20) != NULL) {
+    printf("errors: wrong iv length accepted for streaming\n");
+    return -1;
+  }
+  // only the job with a wrong iv length fails, not the other one using the same cipher
+  struct batch_job jobs[2] = {
+    {data, 64, key, iv, 12, NULL, CIPHER_CHACHA20},
+    {data2, 64, key, iv, 16, NULL, CIPHER_CHACHA20},
+  };
+  int batch_status[2];
+  char untouched[64];
+  memcpy(untouched, data2, 64);
+  encrypt_batch(jobs, 2, batch_status, wrapper);
+  if (batch_status[0] != STATUS_OK || batch_status[1] != STATUS_INVALID_IV_LENGTH || memcmp(data2, untouched, 64)) {
+    printf("errors: wrong iv length accepted in a batch\n");
+    return -1;
+  }
+  // undo the first job
+  encrypt(data, 64, key, iv, 12, wrapper, NULL, CIPHER_CHACHA20);
+  if (encrypt(data, 64, key, iv, 12, wrapper, NULL, 0) != STATUS_OK) {
+    printf("errors: wrapper unusable after a failure\n");
+    return -1;
+  }
+  return 0;
+}
 
 int main()
 {
@@ -82,6 +1157,11 @@ int main()
     exit(2);
   }
 
+  if (test_aead(wrapper) || test_ctr(wrapper) || test_blocks(wrapper) || test_xts(wrapper) || test_mac(wrapper) || test_l1(wrapper) || test_stream(wrapper) || test_segments(wrapper) || test_vectors(wrapper) || test_async(wrapper) || test_batch(wrapper) || test_into(wrapper) || test_dma_misuse(wrapper)
+      || test_errors(wrapper)) {
+    exit(1);
+  }
+
   lennn[0] = LEN;
   for(int i = 0; i < 32; i++){
     key[i] = 0;
@@ -91,6 +1171,7 @@ int main()
   }
 
   printf("iteration: %d\n", LEN);
+  printf("l2, pipeline depth: %d, layout: %s\n", PIPELINE_DEPTH, L1_LAYOUT);
   INIT_STATS();
 
   // executing the code multiple times to perform average statistics
@@ -99,11 +1180,51 @@ int main()
     data[i] = 0;
   }
   START_STATS();
-  encrypt(data, lennn[0], key, iv, wrapper, NULL, 0);
+  encrypt(data, lennn[0], key, iv, 12, wrapper, NULL, 0);
   STOP_STATS();
+  if (_k >= HOTTING) CLUSTER_STATS(wrapper);
 
   // end of the performance statistics loop
   EXIT_STATS_LOOP();
+
+  // ChaCha round variants on the same L2 data, in FC cycles per byte
+  static const struct { int cipher; const char* name; } variants[] = {
+    {CIPHER_CHACHA20, "chacha20 (generic)"},
+    {CIPHER_CHACHA20_PULP, "chacha20"},
+    {CIPHER_CHACHA12_PULP, "chacha12"},
+    {CIPHER_CHACHA8_PULP, "chacha8"},
+  };
+  for (int v = 0; v < sizeof(variants) / sizeof(variants[0]); v++) {
+    printf("l2, %s\n", variants[v].name);
+    ENTER_STATS_LOOP();
+    START_STATS();
+    encrypt(data, lennn[0], key, iv, 12, wrapper, NULL, variants[v].cipher);
+    STOP_STATS();
+    if (_k >= HOTTING) CLUSTER_STATS(wrapper);
+    EXIT_STATS_LOOP();
+    unsigned long cpb = _cycles / REPEAT * 100 / lennn[0];
+    printf("%s: %lu.%02lu cycles/byte\n", variants[v].name, cpb / 100, cpb % 100);
+  }
+
+  // same benchmark with data in HyperRAM, where DMA latency matters the most
+  pi_hyperram_conf_init(&ram_conf);
+  pi_open_from_conf(&ram, &ram_conf);
+  if (pi_ram_open(&ram) || pi_ram_alloc(&ram, &ram_ptr, LEN)) {
+    printf("unable to use HyperRAM\n");
+    exit(3);
+  }
+  pi_ram_write(&ram, ram_ptr, data, LEN);
+  printf("hyperram, pipeline depth: %d, layout: %s\n", PIPELINE_DEPTH, L1_LAYOUT);
+  {
+    ENTER_STATS_LOOP();
+    START_STATS();
+    encrypt((char *)ram_ptr, lennn[0], key, iv, 12, wrapper, &ram, 0);
+    STOP_STATS();
+    if (_k >= HOTTING) CLUSTER_STATS(wrapper);
+    EXIT_STATS_LOOP();
+  }
+  pi_ram_free(&ram, ram_ptr, LEN);
+  pi_ram_close(&ram);
   // encrypt_serial_orig(data3, i, key, iv);
   
     // for(int j = 0; j < i; j++){
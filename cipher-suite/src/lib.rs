@@ -5,11 +5,14 @@
 extern crate alloc;
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use cipher::{IvSizeUser, KeySizeUser, Unsigned};
 use core::ptr::NonNull;
 use generic_array::GenericArray;
-use pulp_sdk_rust::{abort_all, GlobalAllocator, PiDevice, Cluster};
-use pulp_wrapper::{PulpWrapper, SourceLocation};
+use pulp_sdk_rust::{abort_all, Cluster, GlobalAllocator, PiDevice, Platform, StackSize, Target};
+use pulp_wrapper::{
+    carry_le, BufLayout, DeriveIv, ExtendedNonce, Job, KeySchedule, PulpWrapper, RunJob, SourceLocation, StreamSession, WrapperError,
+};
 // This should not actually be used, as it's not clear from the context what the default allocation is
 #[global_allocator]
 static DEFAULT_ALLOCATOR: GlobalAllocator = GlobalAllocator;
@@ -23,29 +26,290 @@ fn panic_handler(_info: &PanicInfo) -> ! {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Cipher {
     ChaCha20Pulp,
     ChaCha20,
     Aes128Ctr,
+    Aes128Gcm,
+    Aes256Gcm,
+    XChaCha20,
+    Salsa20,
+    XSalsa20,
+    /// ChaCha with 12 rounds, using the same Xpulp-optimized implementation as [Cipher::ChaCha20Pulp]
+    ChaCha12Pulp,
+    /// ChaCha with 8 rounds, using the same Xpulp-optimized implementation as [Cipher::ChaCha20Pulp]
+    ChaCha8Pulp,
+    /// AES-CTR with a 32 bit little-endian counter in the first iv word, like [Cipher::Aes128Ctr]
+    Aes192Ctr,
+    Aes256Ctr,
+    /// AES-CTR with a 64 bit big-endian counter in the last 8 bytes of the iv
+    Aes128Ctr64BE,
+    Aes192Ctr64BE,
+    Aes256Ctr64BE,
+    /// AES-CTR with the whole iv as a 128 bit big-endian counter (NIST SP 800-38A)
+    Aes128Ctr128BE,
+    Aes192Ctr128BE,
+    Aes256Ctr128BE,
+    /// AES-CBC, only decryption is supported (see [decrypt_blocks])
+    Aes128Cbc,
+    Aes192Cbc,
+    Aes256Cbc,
+    /// AES-ECB, see [encrypt_blocks] and [decrypt_blocks]
+    Aes128Ecb,
+    Aes192Ecb,
+    Aes256Ecb,
+    /// AES-XTS for storage encryption, see [encrypt_xts] and [decrypt_xts]
+    Aes128Xts,
+    Aes256Xts,
+    /// MACs, see [compute_mac]
+    Poly1305,
+    Aes128Pmac,
+    Aes256Pmac,
+    Aes128Cmac,
+    Aes256Cmac,
+}
+
+/// Number of [Cipher] variants, [Cipher::Aes256Cmac] must stay the last one
+const CIPHERS: usize = Cipher::Aes256Cmac as usize + 1;
+
+/// Outcome of the functions of this library, [Status::Ok] (0) on success
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    TagMismatch,
+    UnsupportedCipher,
+    UnsupportedLocation,
+    KeystreamOverflow,
+    InvalidLength,
+    AllocError,
+    DmaError,
+    InvalidState,
+    /// The iv length does not match the iv size of the cipher
+    InvalidIvLength,
+}
+
+impl From<WrapperError> for Status {
+    fn from(err: WrapperError) -> Self {
+        match err {
+            WrapperError::UnsupportedLocation => Self::UnsupportedLocation,
+            WrapperError::KeystreamOverflow => Self::KeystreamOverflow,
+            WrapperError::InvalidLength => Self::InvalidLength,
+            WrapperError::Alloc => Self::AllocError,
+            WrapperError::Dma => Self::DmaError,
+            WrapperError::TagMismatch => Self::TagMismatch,
+            WrapperError::InvalidState => Self::InvalidState,
+        }
+    }
+}
+
+impl<T> From<Result<T, WrapperError>> for Status {
+    fn from(res: Result<T, WrapperError>) -> Self {
+        res.map_or_else(Self::from, |_| Self::Ok)
+    }
+}
+
+impl<T> From<Result<T, Status>> for Status {
+    fn from(res: Result<T, Status>) -> Self {
+        res.err().unwrap_or(Self::Ok)
+    }
+}
+
+// Evaluates to Err(Status::InvalidIvLength) if $iv_len is not the iv size of $cipher
+macro_rules! extract_iv {
+    ($cipher:ty, $iv:expr, $iv_len:expr) => {{
+        if $iv_len == <$cipher as IvSizeUser>::IvSize::USIZE {
+            Ok(GenericArray::from_slice(core::slice::from_raw_parts($iv, $iv_len)))
+        } else {
+            Err(Status::InvalidIvLength)
+        }
+    }};
 }
 
 macro_rules! extract_key_iv {
-    ($cipher:ty, $key:expr, $iv:expr) => {{
-        let key = GenericArray::from_slice(core::slice::from_raw_parts(
-            $key,
-            <$cipher as KeySizeUser>::KeySize::USIZE,
-        ));
-        let iv = GenericArray::from_slice(core::slice::from_raw_parts(
-            $iv,
-            <$cipher as IvSizeUser>::IvSize::USIZE,
-        ));
-        (key, iv)
+    ($cipher:ty, $key:expr, $iv:expr, $iv_len:expr) => {{
+        if $iv_len == <$cipher as IvSizeUser>::IvSize::USIZE {
+            let key = GenericArray::from_slice(core::slice::from_raw_parts(
+                $key,
+                <$cipher as KeySizeUser>::KeySize::USIZE,
+            ));
+            let iv = GenericArray::from_slice(core::slice::from_raw_parts($iv, $iv_len));
+            Ok((key, iv))
+        } else {
+            Err(Status::InvalidIvLength)
+        }
+    }};
+}
+
+macro_rules! extract_key_iv_gcm {
+    ($cipher:ty, $key:expr, $iv:expr, $iv_len:expr) => {{
+        if $iv_len == pulp_wrapper::GCM_IV_LEN {
+            let key = GenericArray::from_slice(core::slice::from_raw_parts(
+                $key,
+                <$cipher as KeySizeUser>::KeySize::USIZE,
+            ));
+            let iv = &*($iv as *const [u8; pulp_wrapper::GCM_IV_LEN]);
+            Ok((key, iv))
+        } else {
+            Err(Status::InvalidIvLength)
+        }
     }};
 }
 
+// Expands to $stream with the type $C bound to the cipher for the ciphers run as is by the cores,
+// to $extended with $X bound to the cipher for the ciphers with an extended nonce, and to $other
+// for the ciphers that are only available as AEAD, block modes or XTS and for the MACs.
+// AES-CTR ciphers, whose key schedule can be shared (see [KeySchedule]), expand to $scheduled
+// with $A bound to the cipher if given, to $stream otherwise.
+macro_rules! match_cipher {
+    ($cipher:expr, $C:ident => $stream:expr, $X:ident => $extended:expr, _ => $other:expr $(,)?) => {
+        match_cipher!($cipher, $C => $stream, aes $C => $stream, $X => $extended, _ => $other)
+    };
+    (
+        $cipher:expr,
+        $C:ident => $stream:expr,
+        aes $A:ident => $scheduled:expr,
+        $X:ident => $extended:expr,
+        _ => $other:expr $(,)?
+    ) => {
+        match $cipher {
+            Cipher::ChaCha20Pulp => {
+                type $C = chacha20::ChaCha20;
+                $stream
+            }
+            Cipher::ChaCha12Pulp => {
+                type $C = chacha20::ChaCha12;
+                $stream
+            }
+            Cipher::ChaCha8Pulp => {
+                type $C = chacha20::ChaCha8;
+                $stream
+            }
+            Cipher::ChaCha20 => {
+                type $C = chacha20_orig::ChaCha20;
+                $stream
+            }
+            Cipher::Salsa20 => {
+                type $C = salsa20::Salsa20;
+                $stream
+            }
+            Cipher::Aes128Ctr => {
+                type $A = Aes128Ctr;
+                $scheduled
+            }
+            Cipher::Aes192Ctr => {
+                type $A = Aes192Ctr;
+                $scheduled
+            }
+            Cipher::Aes256Ctr => {
+                type $A = Aes256Ctr;
+                $scheduled
+            }
+            Cipher::Aes128Ctr64BE => {
+                type $A = Aes128Ctr64BE;
+                $scheduled
+            }
+            Cipher::Aes192Ctr64BE => {
+                type $A = Aes192Ctr64BE;
+                $scheduled
+            }
+            Cipher::Aes256Ctr64BE => {
+                type $A = Aes256Ctr64BE;
+                $scheduled
+            }
+            Cipher::Aes128Ctr128BE => {
+                type $A = Aes128Ctr128BE;
+                $scheduled
+            }
+            Cipher::Aes192Ctr128BE => {
+                type $A = Aes192Ctr128BE;
+                $scheduled
+            }
+            Cipher::Aes256Ctr128BE => {
+                type $A = Aes256Ctr128BE;
+                $scheduled
+            }
+            Cipher::XChaCha20 => {
+                type $X = chacha20_orig::XChaCha20;
+                $extended
+            }
+            Cipher::XSalsa20 => {
+                type $X = salsa20::XSalsa20;
+                $extended
+            }
+            Cipher::Aes128Gcm
+            | Cipher::Aes256Gcm
+            | Cipher::Aes128Cbc
+            | Cipher::Aes192Cbc
+            | Cipher::Aes256Cbc
+            | Cipher::Aes128Ecb
+            | Cipher::Aes192Ecb
+            | Cipher::Aes256Ecb
+            | Cipher::Aes128Xts
+            | Cipher::Aes256Xts
+            | Cipher::Poly1305
+            | Cipher::Aes128Pmac
+            | Cipher::Aes256Pmac
+            | Cipher::Aes128Cmac
+            | Cipher::Aes256Cmac => $other,
+        }
+    };
+}
+
 type Aes128Ctr = ctr::Ctr32LE<aes::Aes128>;
-const CLUSTER_L1_BUFFER_LEN: usize = 8192;
-const CORES: usize = parse_cores_u8(core::env!("CORES"));
+type Aes192Ctr = ctr::Ctr32LE<aes::Aes192>;
+type Aes256Ctr = ctr::Ctr32LE<aes::Aes256>;
+type Aes128Ctr64BE = ctr::Ctr64BE<aes::Aes128>;
+type Aes192Ctr64BE = ctr::Ctr64BE<aes::Aes192>;
+type Aes256Ctr64BE = ctr::Ctr64BE<aes::Aes256>;
+type Aes128Ctr128BE = ctr::Ctr128BE<aes::Aes128>;
+type Aes192Ctr128BE = ctr::Ctr128BE<aes::Aes192>;
+type Aes256Ctr128BE = ctr::Ctr128BE<aes::Aes256>;
+const CLUSTER_L1_BUFFER_LEN: usize = <Target as Platform>::DEFAULT_L1_BUFFER_LEN;
+// Can be overridden at build time, otherwise use all cores of the selected platform
+const CORES: usize = match core::option_env!("CORES") {
+    Some(cores) => parse_cores_u8(cores),
+    None => <Target as Platform>::CLUSTER_CORES,
+};
+
+// Number of L1 buffers used to pipeline DMA transfers, can be overridden at build time
+const PIPELINE_DEPTH: usize = match core::option_env!("PIPELINE_DEPTH") {
+    Some(depth) => parse_usize(depth),
+    None => 3,
+};
+type Wrapper = PulpWrapper<CORES, CLUSTER_L1_BUFFER_LEN, PIPELINE_DEPTH>;
+
+// Placement of the L1 buffers in the memory banks, `contiguous` (default) or `interleaved`
+const L1_LAYOUT: BufLayout = match core::option_env!("L1_LAYOUT") {
+    Some(layout) if layout.as_bytes()[0] == b'i' => BufLayout::Interleaved,
+    _ => BufLayout::Contiguous,
+};
+
+/// Decimal number given at build time, rejecting anything else
+const fn parse_usize(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    if bytes.is_empty() {
+        panic!("expected a number, got an empty string");
+    }
+    let mut value: usize = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = bytes[i];
+        if !digit.is_ascii_digit() {
+            panic!("expected a number");
+        }
+        value = match value.checked_mul(10) {
+            Some(value) => value.saturating_add((digit - b'0') as usize),
+            None => usize::MAX,
+        };
+        if value == usize::MAX {
+            panic!("number too big");
+        }
+        i += 1;
+    }
+    value
+}
 
 const fn parse_cores_u8(s: &str) -> usize {
     let cores = (s.as_bytes()[0] - b'0') as usize;
@@ -60,13 +324,22 @@ const fn parse_cores_u8(s: &str) -> usize {
 
 
 /// Initialize the cluster and the cluster wrapper wrapper in L2 memory
+///
+/// Returns NULL if the cluster could not be opened or the wrapper buffers do not fit in L1
 #[no_mangle]
 pub extern "C" fn cluster_init() -> *mut cty::c_void {
-    let cluster = <Cluster<CORES>>::new().unwrap();
-    let wrapper = Box::new_in(
-        <PulpWrapper<CORES,CLUSTER_L1_BUFFER_LEN>>::new(cluster),
-        pulp_sdk_rust::L2Allocator,
-    );
+    let cluster = match <Cluster<CORES>>::new() {
+        Ok(cluster) => cluster,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    let wrapper = match Wrapper::new_with_layout(cluster, StackSize::DEFAULT, L1_LAYOUT) {
+        Ok(wrapper) => wrapper,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    let wrapper = match Box::try_new_in(wrapper, pulp_sdk_rust::L2Allocator) {
+        Ok(wrapper) => wrapper,
+        Err(_) => return core::ptr::null_mut(),
+    };
     Box::into_raw(wrapper) as *mut cty::c_void
 }
 
@@ -74,8 +347,9 @@ pub extern "C" fn cluster_init() -> *mut cty::c_void {
 ///
 /// # Safety:
 /// * data must be valid to read / write for len bytes and must be in L2 memory
-/// * key must be valid to read for: 32 bytes
-/// * iv must be valid to read for 12 bytes
+/// * key must be valid to read for the key size of the cipher: 32 bytes (16 for AES-128, 24 for AES-192)
+/// * iv must be valid to read for iv_len bytes, which must be the iv size of the cipher: 12 bytes
+///   (24 for XChaCha20 and XSalsa20, 8 for Salsa20, 16 for AES-CTR)
 /// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
 #[no_mangle]
 pub unsafe extern "C" fn encrypt(
@@ -83,33 +357,1152 @@ pub unsafe extern "C" fn encrypt(
     len: usize,
     key: *const u8,
     iv: *const u8,
+    iv_len: usize,
     wrapper: *mut cty::c_void,
     ram_device: *mut PiDevice,
     cipher: Cipher,
-) {
-    let wrapper = (wrapper as *mut PulpWrapper<CORES, CLUSTER_L1_BUFFER_LEN>)
-        .as_mut()
-        .unwrap();
+) -> Status {
+    run(data, len, key, iv, iv_len, wrapper, source_location(ram_device), cipher).into()
+}
+
+/// Encrypt / decrypt data which is already in the cluster L1 memory, without any DMA transfer
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L1 memory
+/// * key and iv must satisfy the requirements of [encrypt]
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_l1(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    cipher: Cipher,
+) -> Status {
+    run(data, len, key, iv, iv_len, wrapper, SourceLocation::L1, cipher).into()
+}
+
+/// Encrypt / decrypt len bytes from source into dest using the provided cipher, leaving source untouched
+///
+/// Each of source and dest is in RAM if its ram device is given, in L2 otherwise.
+///
+/// # Safety:
+/// * source must be valid to read and dest valid to write for len bytes, and they must not overlap
+/// * key and iv must satisfy the requirements of [encrypt]
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_into(
+    source: *const u8,
+    dest: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    source_ram: *mut PiDevice,
+    dest_ram: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_into(
+        source,
+        dest,
+        len,
+        key,
+        iv,
+        iv_len,
+        wrapper,
+        source_location(source_ram),
+        source_location(dest_ram),
+        cipher,
+    )
+    .into()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_into(
+    source: *const u8,
+    dest: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    source_loc: SourceLocation,
+    dest_loc: SourceLocation,
+    cipher: Cipher,
+) -> Result<(), Status> {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let source = core::slice::from_raw_parts(source, len);
+    let dest = core::slice::from_raw_parts_mut(dest, len);
+    match_cipher!(cipher,
+        C => {
+            let (key, iv) = extract_key_iv!(C, key, iv, iv_len)?;
+            wrapper.run_into::<C>(source, source_loc, dest, dest_loc, key, iv)?;
+        },
+        X => {
+            let (key, iv) = extract_key_iv!(X, key, iv, iv_len)?;
+            let (key, iv) = X::derive(key, iv);
+            wrapper.run_into::<<X as ExtendedNonce>::Inner>(source, source_loc, dest, dest_loc, &key, &iv)?;
+        },
+        // only available as AEAD, block modes or XTS, or not a cipher at all
+        _ => return Err(Status::UnsupportedCipher),
+    );
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    location: SourceLocation,
+    cipher: Cipher,
+) -> Result<(), Status> {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
     let data = core::slice::from_raw_parts_mut(data, len);
-    let location = if let Some(device) = NonNull::new(ram_device) {
-        SourceLocation::Ram(device)
-    } else {
-        SourceLocation::L2
+    match_cipher!(cipher,
+        C => {
+            let (key, iv) = extract_key_iv!(C, key, iv, iv_len)?;
+            wrapper.run::<C>(data, key, iv, location)?;
+        },
+        aes A => {
+            let (key, iv) = extract_key_iv!(A, key, iv, iv_len)?;
+            wrapper.run_shared::<A>(data, key, iv, location)?;
+        },
+        X => {
+            let (key, iv) = extract_key_iv!(X, key, iv, iv_len)?;
+            wrapper.run_extended::<X>(data, key, iv, location)?;
+        },
+        // only available as AEAD, block modes or XTS, or not a cipher at all
+        _ => return Err(Status::UnsupportedCipher),
+    );
+    Ok(())
+}
+
+/// AES-CTR cipher with its key expanded once by [context_init], see [encrypt_context]
+struct CipherContext {
+    cipher: Cipher,
+    schedule: Schedule,
+}
+
+enum Schedule {
+    Aes128(aes::Aes128),
+    Aes192(aes::Aes192),
+    Aes256(aes::Aes256),
+}
+
+/// Expand the key of an AES-CTR cipher once, for repeated calls to [encrypt_context]
+/// with the same key and any iv.
+///
+/// Returns NULL if the cipher has no key schedule (i.e. it is not AES-CTR) or the context
+/// can't be allocated.
+///
+/// # Safety:
+/// * key must be valid to read for the key size of the cipher: 16, 24 or 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn context_init(key: *const u8, cipher: Cipher) -> *mut cty::c_void {
+    let schedule = match cipher {
+        Cipher::Aes128Ctr | Cipher::Aes128Ctr64BE | Cipher::Aes128Ctr128BE => Schedule::Aes128(expand_key(key)),
+        Cipher::Aes192Ctr | Cipher::Aes192Ctr64BE | Cipher::Aes192Ctr128BE => Schedule::Aes192(expand_key(key)),
+        Cipher::Aes256Ctr | Cipher::Aes256Ctr64BE | Cipher::Aes256Ctr128BE => Schedule::Aes256(expand_key(key)),
+        _ => return core::ptr::null_mut(),
     };
-    match cipher {
+    match Box::try_new_in(CipherContext { cipher, schedule }, pulp_sdk_rust::L2Allocator) {
+        Ok(context) => Box::into_raw(context) as *mut cty::c_void,
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+unsafe fn expand_key<B: cipher::KeyInit>(key: *const u8) -> B {
+    B::new(GenericArray::from_slice(core::slice::from_raw_parts(key, B::KeySize::USIZE)))
+}
+
+/// Same as [encrypt] with the key schedule of [context], without expanding the key again
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * context must be a valid pointer returned by [context_init]
+/// * iv must be valid to read for iv_len bytes, which must be 16
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_context(
+    data: *mut u8,
+    len: usize,
+    context: *const cty::c_void,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+) -> Status {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let context = (context as *const CipherContext).as_ref().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let location = source_location(ram_device);
+    let res = match (context.cipher, &context.schedule) {
+        (Cipher::Aes128Ctr, Schedule::Aes128(aes)) => {
+            run_scheduled::<Aes128Ctr>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes192Ctr, Schedule::Aes192(aes)) => {
+            run_scheduled::<Aes192Ctr>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes256Ctr, Schedule::Aes256(aes)) => {
+            run_scheduled::<Aes256Ctr>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes128Ctr64BE, Schedule::Aes128(aes)) => {
+            run_scheduled::<Aes128Ctr64BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes192Ctr64BE, Schedule::Aes192(aes)) => {
+            run_scheduled::<Aes192Ctr64BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes256Ctr64BE, Schedule::Aes256(aes)) => {
+            run_scheduled::<Aes256Ctr64BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes128Ctr128BE, Schedule::Aes128(aes)) => {
+            run_scheduled::<Aes128Ctr128BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes192Ctr128BE, Schedule::Aes192(aes)) => {
+            run_scheduled::<Aes192Ctr128BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        (Cipher::Aes256Ctr128BE, Schedule::Aes256(aes)) => {
+            run_scheduled::<Aes256Ctr128BE>(wrapper, data, aes, iv, iv_len, location)
+        }
+        // context_init pairs every cipher with the key schedule of its key size
+        _ => unreachable!(),
+    };
+    res.into()
+}
+
+unsafe fn run_scheduled<C: KeySchedule>(
+    wrapper: &mut Wrapper,
+    data: &mut [u8],
+    schedule: &C::Schedule,
+    iv: *const u8,
+    iv_len: usize,
+    location: SourceLocation,
+) -> Result<(), Status> {
+    let iv = extract_iv!(C, iv, iv_len)?;
+    wrapper.run_scheduled::<C>(data, schedule, iv, location)?;
+    Ok(())
+}
+
+/// Release a context returned by [context_init]
+///
+/// # Safety:
+/// * context must be a valid pointer returned by [context_init], and is invalid afterwards
+#[no_mangle]
+pub unsafe extern "C" fn context_free(context: *mut cty::c_void) {
+    let _context = Box::from_raw_in(context as *mut CipherContext, pulp_sdk_rust::L2Allocator);
+}
+
+/// Handle returned by [encrypt_start]
+struct EncryptJob {
+    // declared first, so that it is waited on before the subkey is released
+    job: RunJob<'static, CORES, CLUSTER_L1_BUFFER_LEN, PIPELINE_DEPTH>,
+    _subkey: Option<Box<Subkey, pulp_sdk_rust::L2Allocator>>,
+}
+
+/// Key and iv derived on the FC for ciphers with an extended nonce, see [ExtendedNonce]
+#[derive(Default)]
+struct Subkey {
+    key: [u8; 32],
+    iv: [u8; 12],
+}
+
+impl EncryptJob {
+    unsafe fn start<C>(
+        wrapper: &'static Wrapper,
+        data: &'static mut [u8],
+        key: *const u8,
+        iv: *const u8,
+        iv_len: usize,
+        location: SourceLocation,
+    ) -> Result<Self, Status>
+    where
+        C: cipher::StreamCipher + cipher::StreamCipherSeek + cipher::KeyIvInit + pulp_wrapper::KeystreamBlock,
+    {
+        let (key, iv) = extract_key_iv!(C, key, iv, iv_len)?;
+        Ok(Self {
+            job: wrapper.run_async::<C>(data, key, iv, location)?,
+            _subkey: None,
+        })
+    }
+
+    unsafe fn start_extended<X: ExtendedNonce>(
+        wrapper: &'static Wrapper,
+        data: &'static mut [u8],
+        key: *const u8,
+        iv: *const u8,
+        iv_len: usize,
+        location: SourceLocation,
+    ) -> Result<Self, Status> {
+        let (key, iv) = extract_key_iv!(X, key, iv, iv_len)?;
+        let (key, iv) = X::derive(key, iv);
+        // the job borrows the subkey, which must not move until it completes
+        let mut subkey = Box::try_new_in(Subkey::default(), pulp_sdk_rust::L2Allocator)
+            .map_err(|_| Status::AllocError)?;
+        subkey.key[..key.len()].copy_from_slice(&key);
+        subkey.iv[..iv.len()].copy_from_slice(&iv);
+        let mut job = Self::start::<X::Inner>(
+            wrapper,
+            data,
+            subkey.key.as_ptr(),
+            subkey.iv.as_ptr(),
+            iv.len(),
+            location,
+        )?;
+        job._subkey = Some(subkey);
+        Ok(job)
+    }
+}
+
+/// Start encrypting / decrypting data in L2 or ram memory and return without waiting for the
+/// cluster, so that the next input can be prepared in the meantime.
+///
+/// On success, [job] is set to a handle that must be passed to [encrypt_wait].
+/// Jobs are processed in the order they were started, at most two can be in flight:
+/// starting a third one before waiting on the first fails with [Status::InvalidState].
+///
+/// # Safety:
+/// * same as [encrypt], and data, key and iv must stay valid until [encrypt_wait] returns
+/// * job must be valid to write
+/// * every job must be waited on before the wrapper is used by functions other than
+///   [encrypt_start] and [encrypt_wait], or closed
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_start(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+    job: *mut *mut cty::c_void,
+) -> Status {
+    let wrapper = (wrapper as *const Wrapper).as_ref().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let location = source_location(ram_device);
+    let res = match_cipher!(cipher,
+        C => EncryptJob::start::<C>(wrapper, data, key, iv, iv_len, location),
+        X => EncryptJob::start_extended::<X>(wrapper, data, key, iv, iv_len, location),
+        _ => Err(Status::UnsupportedCipher),
+    );
+    // if the handle can't be allocated, the job is waited on right away
+    let handle = res.and_then(|handle| {
+        Box::try_new_in(handle, pulp_sdk_rust::L2Allocator).map_err(|_| Status::AllocError)
+    });
+    match handle {
+        Ok(handle) => {
+            *job = Box::into_raw(handle) as *mut cty::c_void;
+            Status::Ok
+        }
+        Err(err) => err,
+    }
+}
+
+/// Wait for a job started by [encrypt_start] to complete and release it
+///
+/// # Safety:
+/// * job must be a handle returned by [encrypt_start] that was not waited on yet
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_wait(job: *mut cty::c_void) -> Status {
+    let job = Box::from_raw_in(job as *mut EncryptJob, pulp_sdk_rust::L2Allocator);
+    job.job.wait().into()
+}
+
+type Session<C> = StreamSession<'static, C, CORES, CLUSTER_L1_BUFFER_LEN, PIPELINE_DEPTH>;
+
+enum StreamState {
+    ChaCha20Pulp(Session<chacha20::ChaCha20>),
+    ChaCha12Pulp(Session<chacha20::ChaCha12>),
+    ChaCha8Pulp(Session<chacha20::ChaCha8>),
+    ChaCha20(Session<chacha20_orig::ChaCha20>),
+    Salsa20(Session<salsa20::Salsa20>),
+    Aes128Ctr(Session<Aes128Ctr>),
+    Aes192Ctr(Session<Aes192Ctr>),
+    Aes256Ctr(Session<Aes256Ctr>),
+    Aes128Ctr64BE(Session<Aes128Ctr64BE>),
+    Aes192Ctr64BE(Session<Aes192Ctr64BE>),
+    Aes256Ctr64BE(Session<Aes256Ctr64BE>),
+    Aes128Ctr128BE(Session<Aes128Ctr128BE>),
+    Aes192Ctr128BE(Session<Aes192Ctr128BE>),
+    Aes256Ctr128BE(Session<Aes256Ctr128BE>),
+}
+
+// Expands to $body with $s bound to the session of any variant of $state
+macro_rules! with_session {
+    ($state:expr, $s:ident => $body:expr) => {
+        match $state {
+            StreamState::ChaCha20Pulp($s) => $body,
+            StreamState::ChaCha12Pulp($s) => $body,
+            StreamState::ChaCha8Pulp($s) => $body,
+            StreamState::ChaCha20($s) => $body,
+            StreamState::Salsa20($s) => $body,
+            StreamState::Aes128Ctr($s) => $body,
+            StreamState::Aes192Ctr($s) => $body,
+            StreamState::Aes256Ctr($s) => $body,
+            StreamState::Aes128Ctr64BE($s) => $body,
+            StreamState::Aes192Ctr64BE($s) => $body,
+            StreamState::Aes256Ctr64BE($s) => $body,
+            StreamState::Aes128Ctr128BE($s) => $body,
+            StreamState::Aes192Ctr128BE($s) => $body,
+            StreamState::Aes256Ctr128BE($s) => $body,
+        }
+    };
+}
+
+/// Start encrypting / decrypting a stream that will be provided in pieces with [stream_update].
+///
+/// Returns NULL if the cipher can't be used for streaming, the iv length does not match
+/// or the session can't be allocated.
+///
+/// # Safety:
+/// * key and iv must satisfy the requirements of [encrypt]
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+///   and must not be used by other functions until [stream_finish] is called
+#[no_mangle]
+pub unsafe extern "C" fn stream_init(
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    cipher: Cipher,
+) -> *mut cty::c_void {
+    stream_start(key, iv, iv_len, wrapper, cipher, false)
+}
+
+/// Same as [stream_init], but the stream continues past the end of the keystream of the iv.
+///
+/// Every 2^32 - 1 blocks the keystream restarts with a nonce derived as if the block counter
+/// carried into the next 32 bit word of the iv: the first nonce word for ChaCha20 and its
+/// reduced-round variants (of the derived nonce for XChaCha20), the second iv word for AES-CTR
+/// with a 32 bit counter.
+/// Salsa20, XSalsa20 and AES-CTR with a 64 or 128 bit counter never need a new nonce.
+///
+/// # Safety:
+/// * same as [stream_init]
+#[no_mangle]
+pub unsafe extern "C" fn stream_init_segmented(
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    cipher: Cipher,
+) -> *mut cty::c_void {
+    stream_start(key, iv, iv_len, wrapper, cipher, true)
+}
+
+unsafe fn stream_start(
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    cipher: Cipher,
+    segmented: bool,
+) -> *mut cty::c_void {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let state = match stream_state(key, iv, iv_len, wrapper, cipher, segmented) {
+        Ok(state) => state,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    let state = match Box::try_new_in(state, pulp_sdk_rust::L2Allocator) {
+        Ok(state) => state,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    Box::into_raw(state) as *mut cty::c_void
+}
+
+unsafe fn stream_state(
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: &'static mut Wrapper,
+    cipher: Cipher,
+    segmented: bool,
+) -> Result<StreamState, Status> {
+    let state = match cipher {
         Cipher::ChaCha20 => {
-            let (key, iv) = extract_key_iv!(chacha20_orig::ChaCha20, key, iv);
-            wrapper.run::<chacha20_orig::ChaCha20>(data, key, iv, location)
+            let (key, iv) = extract_key_iv!(chacha20_orig::ChaCha20, key, iv, iv_len)?;
+            StreamState::ChaCha20(segment(wrapper.session(key, iv), segmented, carry_le::<_, 0>))
         }
         Cipher::ChaCha20Pulp => {
-            let (key, iv) = extract_key_iv!(chacha20::ChaCha20, key, iv);
-            wrapper.run::<chacha20::ChaCha20>(data, key, iv, location)
+            let (key, iv) = extract_key_iv!(chacha20::ChaCha20, key, iv, iv_len)?;
+            StreamState::ChaCha20Pulp(segment(wrapper.session(key, iv), segmented, carry_le::<_, 0>))
+        }
+        Cipher::ChaCha12Pulp => {
+            let (key, iv) = extract_key_iv!(chacha20::ChaCha12, key, iv, iv_len)?;
+            StreamState::ChaCha12Pulp(segment(wrapper.session(key, iv), segmented, carry_le::<_, 0>))
+        }
+        Cipher::ChaCha8Pulp => {
+            let (key, iv) = extract_key_iv!(chacha20::ChaCha8, key, iv, iv_len)?;
+            StreamState::ChaCha8Pulp(segment(wrapper.session(key, iv), segmented, carry_le::<_, 0>))
+        }
+        Cipher::XChaCha20 => {
+            let (key, iv) = extract_key_iv!(chacha20_orig::XChaCha20, key, iv, iv_len)?;
+            let session = wrapper.session_extended::<chacha20_orig::XChaCha20>(key, iv);
+            StreamState::ChaCha20(segment(session, segmented, carry_le::<_, 0>))
+        }
+        Cipher::Salsa20 => {
+            let (key, iv) = extract_key_iv!(salsa20::Salsa20, key, iv, iv_len)?;
+            StreamState::Salsa20(wrapper.session(key, iv))
+        }
+        Cipher::XSalsa20 => {
+            let (key, iv) = extract_key_iv!(salsa20::XSalsa20, key, iv, iv_len)?;
+            StreamState::Salsa20(wrapper.session_extended::<salsa20::XSalsa20>(key, iv))
         }
         Cipher::Aes128Ctr => {
-            let (key, iv) = extract_key_iv!(Aes128Ctr, key, iv);
-            wrapper.run::<Aes128Ctr>(data, key, iv, location)
+            let (key, iv) = extract_key_iv!(Aes128Ctr, key, iv, iv_len)?;
+            StreamState::Aes128Ctr(segment(wrapper.session(key, iv), segmented, carry_le::<_, 4>))
+        }
+        Cipher::Aes192Ctr => {
+            let (key, iv) = extract_key_iv!(Aes192Ctr, key, iv, iv_len)?;
+            StreamState::Aes192Ctr(segment(wrapper.session(key, iv), segmented, carry_le::<_, 4>))
+        }
+        Cipher::Aes256Ctr => {
+            let (key, iv) = extract_key_iv!(Aes256Ctr, key, iv, iv_len)?;
+            StreamState::Aes256Ctr(segment(wrapper.session(key, iv), segmented, carry_le::<_, 4>))
+        }
+        Cipher::Aes128Ctr64BE => {
+            let (key, iv) = extract_key_iv!(Aes128Ctr64BE, key, iv, iv_len)?;
+            StreamState::Aes128Ctr64BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes192Ctr64BE => {
+            let (key, iv) = extract_key_iv!(Aes192Ctr64BE, key, iv, iv_len)?;
+            StreamState::Aes192Ctr64BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes256Ctr64BE => {
+            let (key, iv) = extract_key_iv!(Aes256Ctr64BE, key, iv, iv_len)?;
+            StreamState::Aes256Ctr64BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes128Ctr128BE => {
+            let (key, iv) = extract_key_iv!(Aes128Ctr128BE, key, iv, iv_len)?;
+            StreamState::Aes128Ctr128BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes192Ctr128BE => {
+            let (key, iv) = extract_key_iv!(Aes192Ctr128BE, key, iv, iv_len)?;
+            StreamState::Aes192Ctr128BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes256Ctr128BE => {
+            let (key, iv) = extract_key_iv!(Aes256Ctr128BE, key, iv, iv_len)?;
+            StreamState::Aes256Ctr128BE(wrapper.session(key, iv))
+        }
+        Cipher::Aes128Gcm
+        | Cipher::Aes256Gcm
+        | Cipher::Aes128Cbc
+        | Cipher::Aes192Cbc
+        | Cipher::Aes256Cbc
+        | Cipher::Aes128Ecb
+        | Cipher::Aes192Ecb
+        | Cipher::Aes256Ecb
+        | Cipher::Aes128Xts
+        | Cipher::Aes256Xts
+        | Cipher::Poly1305
+        | Cipher::Aes128Pmac
+        | Cipher::Aes256Pmac
+        | Cipher::Aes128Cmac
+        | Cipher::Aes256Cmac => return Err(Status::UnsupportedCipher),
+    };
+    Ok(state)
+}
+
+/// [session] continuing with the nonces given by [derive_iv] if [segmented]
+fn segment<C>(session: Session<C>, segmented: bool, derive_iv: DeriveIv<C>) -> Session<C>
+where
+    C: cipher::StreamCipher + cipher::StreamCipherSeek + cipher::KeyIvInit + pulp_wrapper::KeystreamBlock,
+{
+    if segmented {
+        session.segmented(derive_iv)
+    } else {
+        session
+    }
+}
+
+/// Encrypt / decrypt the next piece of the stream, continuing where the last call stopped.
+/// On error the stream does not advance.
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * session must be a valid pointer returned by [stream_init]
+#[no_mangle]
+pub unsafe extern "C" fn stream_update(
+    session: *mut cty::c_void,
+    data: *mut u8,
+    len: usize,
+    ram_device: *mut PiDevice,
+) -> Status {
+    let session = (session as *mut StreamState).as_mut().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let location = source_location(ram_device);
+    with_session!(session, s => s.update(data, location).into())
+}
+
+/// Continue the stream from byte offset, e.g. to resume a stream or to skip to a part of it.
+/// Segmented sessions restart with the nonce of the segment containing offset.
+///
+/// # Safety:
+/// * session must be a valid pointer returned by [stream_init]
+#[no_mangle]
+pub unsafe extern "C" fn stream_seek(session: *mut cty::c_void, offset: u64) {
+    let session = (session as *mut StreamState).as_mut().unwrap();
+    with_session!(session, s => s.seek(offset))
+}
+
+/// End the stream and release the session, returning the position reached in the stream
+/// (the total number of bytes processed, unless it was moved with [stream_seek])
+///
+/// # Safety:
+/// * session must be a valid pointer returned by [stream_init], and is invalid afterwards
+#[no_mangle]
+pub unsafe extern "C" fn stream_finish(session: *mut cty::c_void) -> u64 {
+    let session = Box::from_raw_in(session as *mut StreamState, pulp_sdk_rust::L2Allocator);
+    with_session!(*session, s => s.finish())
+}
+
+/// A message of a batch, see [encrypt_batch]
+#[repr(C)]
+pub struct BatchJob {
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+}
+
+/// Encrypt / decrypt many independent messages, each with its own key, iv and cipher,
+/// sharing cluster tasks between messages using the same cipher.
+/// The outcome of each job is written to the corresponding entry of [status]: an invalid job
+/// (e.g. with an iv length mismatch) is not processed and does not affect the others.
+///
+/// # Safety:
+/// * jobs must be valid to read for len jobs, and status valid to write for len entries
+/// * every job must satisfy the requirements of [encrypt]
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_batch(
+    jobs: *const BatchJob,
+    len: usize,
+    status: *mut Status,
+    wrapper: *mut cty::c_void,
+) {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let jobs = core::slice::from_raw_parts(jobs, len);
+    let status = core::slice::from_raw_parts_mut(status, len);
+    // each cipher is run once, when its first job is reached
+    let mut started = [false; CIPHERS];
+    for job in jobs {
+        let cipher = job.cipher;
+        if core::mem::replace(&mut started[cipher as usize], true) {
+            continue;
+        }
+        let res = match_cipher!(cipher,
+            C => run_batch::<C>(wrapper, jobs, status, cipher),
+            X => run_batch_extended::<X>(wrapper, jobs, status, cipher),
+            _ => Err(Status::UnsupportedCipher),
+        );
+        // the jobs of the cipher could not be run at all
+        if let Err(err) = res {
+            for (job, status) in jobs.iter().zip(status.iter_mut()) {
+                if job.cipher == cipher {
+                    *status = err;
+                }
+            }
+        }
+    }
+}
+
+/// Run all jobs of [jobs] using [cipher] in a single batch, writing their outcome to [status]
+unsafe fn run_batch<C>(
+    wrapper: &mut Wrapper,
+    jobs: &[BatchJob],
+    status: &mut [Status],
+    cipher: Cipher,
+) -> Result<(), Status>
+where
+    C: cipher::StreamCipher + cipher::StreamCipherSeek + cipher::KeyIvInit + pulp_wrapper::KeystreamBlock,
+{
+    let mut batch = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    let mut indices = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    batch
+        .try_reserve_exact(jobs.len())
+        .and(indices.try_reserve_exact(jobs.len()))
+        .map_err(|_| Status::AllocError)?;
+    for (i, job) in jobs.iter().enumerate().filter(|(_, job)| job.cipher == cipher) {
+        match extract_key_iv!(C, job.key, job.iv, job.iv_len) {
+            Ok((key, iv)) => {
+                batch.push(Job::<C> {
+                    data: core::slice::from_raw_parts_mut(job.data, job.len),
+                    key,
+                    iv,
+                    loc: source_location(job.ram_device),
+                });
+                indices.push(i);
+            }
+            Err(err) => status[i] = err,
+        }
+    }
+    run_jobs(wrapper, &mut batch, &indices, status)
+}
+
+/// Same as [run_batch] for a cipher with an extended nonce, deriving the subkeys on the FC
+unsafe fn run_batch_extended<X: ExtendedNonce>(
+    wrapper: &mut Wrapper,
+    jobs: &[BatchJob],
+    status: &mut [Status],
+    cipher: Cipher,
+) -> Result<(), Status> {
+    let mut subkeys = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    let mut indices = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    subkeys
+        .try_reserve_exact(jobs.len())
+        .and(indices.try_reserve_exact(jobs.len()))
+        .map_err(|_| Status::AllocError)?;
+    for (i, job) in jobs.iter().enumerate().filter(|(_, job)| job.cipher == cipher) {
+        match extract_key_iv!(X, job.key, job.iv, job.iv_len) {
+            Ok((key, iv)) => {
+                subkeys.push(X::derive(key, iv));
+                indices.push(i);
+            }
+            Err(err) => status[i] = err,
+        }
+    }
+    let mut batch = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    batch
+        .try_reserve_exact(subkeys.len())
+        .map_err(|_| Status::AllocError)?;
+    for (&i, (key, iv)) in indices.iter().zip(&subkeys) {
+        let job = &jobs[i];
+        batch.push(Job::<X::Inner> {
+            data: core::slice::from_raw_parts_mut(job.data, job.len),
+            key,
+            iv,
+            loc: source_location(job.ram_device),
+        });
+    }
+    run_jobs(wrapper, &mut batch, &indices, status)
+}
+
+/// Run the valid jobs of a batch, found at [indices] in the batch given to [encrypt_batch]
+unsafe fn run_jobs<C>(
+    wrapper: &mut Wrapper,
+    batch: &mut [Job<C>],
+    indices: &[usize],
+    status: &mut [Status],
+) -> Result<(), Status>
+where
+    C: cipher::StreamCipher + cipher::StreamCipherSeek + cipher::KeyIvInit + pulp_wrapper::KeystreamBlock,
+{
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let mut results = Vec::new_in(pulp_sdk_rust::L2Allocator);
+    results
+        .try_reserve_exact(batch.len())
+        .map_err(|_| Status::AllocError)?;
+    results.resize(batch.len(), Ok(()));
+    wrapper.run_batch(batch, &mut results)?;
+    for (&i, res) in indices.iter().zip(&results) {
+        status[i] = (*res).into();
+    }
+    Ok(())
+}
+
+/// Encrypt with ChaCha20-Poly1305 or AES-GCM and write the authentication tag to [tag]
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * aad must be valid to read for aad_len bytes
+/// * key must be valid to read for: 32 bytes (16 for AES-128)
+/// * iv must be valid to read for iv_len bytes, which must be 12
+/// * tag must be valid to write for 16 bytes
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_aead(
+    data: *mut u8,
+    len: usize,
+    aad: *const u8,
+    aad_len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    tag: *mut u8,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let aad = core::slice::from_raw_parts(aad, aad_len);
+    let tag = &mut *(tag as *mut [u8; pulp_wrapper::TAG_LEN]);
+    let location = source_location(ram_device);
+    let res = match cipher {
+        Cipher::ChaCha20 => extract_key_iv!(chacha20_orig::ChaCha20, key, iv, iv_len).map(|(key, iv)| {
+            wrapper.seal::<chacha20_orig::ChaCha20>(data, aad, key, iv, location)
+        }),
+        Cipher::ChaCha20Pulp => extract_key_iv!(chacha20::ChaCha20, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.seal::<chacha20::ChaCha20>(data, aad, key, iv, location)),
+        Cipher::Aes128Gcm => extract_key_iv_gcm!(aes::Aes128, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.seal_gcm::<aes::Aes128>(data, aad, key, iv, location)),
+        Cipher::Aes256Gcm => extract_key_iv_gcm!(aes::Aes256, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.seal_gcm::<aes::Aes256>(data, aad, key, iv, location)),
+        _ => return Status::UnsupportedCipher,
+    };
+    match res {
+        Ok(Ok(res)) => {
+            *tag = res;
+            Status::Ok
+        }
+        Ok(Err(err)) => err.into(),
+        Err(err) => err,
+    }
+}
+
+/// Decrypt with ChaCha20-Poly1305 or AES-GCM if the authentication tag in [tag] matches.
+/// The ciphertext is checked before decrypting it: on mismatch, data is left untouched.
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * aad must be valid to read for aad_len bytes
+/// * key must be valid to read for: 32 bytes (16 for AES-128)
+/// * iv must be valid to read for iv_len bytes, which must be 12
+/// * tag must be valid to read for 16 bytes
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn decrypt_aead(
+    data: *mut u8,
+    len: usize,
+    aad: *const u8,
+    aad_len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    tag: *const u8,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let aad = core::slice::from_raw_parts(aad, aad_len);
+    let tag = &*(tag as *const [u8; pulp_wrapper::TAG_LEN]);
+    let location = source_location(ram_device);
+    let res = match cipher {
+        Cipher::ChaCha20 => extract_key_iv!(chacha20_orig::ChaCha20, key, iv, iv_len).map(|(key, iv)| {
+            wrapper.open::<chacha20_orig::ChaCha20>(data, aad, tag, key, iv, location)
+        }),
+        Cipher::ChaCha20Pulp => extract_key_iv!(chacha20::ChaCha20, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.open::<chacha20::ChaCha20>(data, aad, tag, key, iv, location)),
+        Cipher::Aes128Gcm => extract_key_iv_gcm!(aes::Aes128, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.open_gcm::<aes::Aes128>(data, aad, tag, key, iv, location)),
+        Cipher::Aes256Gcm => extract_key_iv_gcm!(aes::Aes256, key, iv, iv_len)
+            .map(|(key, iv)| wrapper.open_gcm::<aes::Aes256>(data, aad, tag, key, iv, location)),
+        _ => return Status::UnsupportedCipher,
+    };
+    match res {
+        Ok(res) => res.into(),
+        Err(err) => err,
+    }
+}
+
+/// Encrypt data with a block cipher mode: only AES-ECB, as CBC encryption can't be split
+/// across cores.
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * len must be a multiple of 16 bytes
+/// * key must be valid to read for the key size of the cipher: 16, 24 or 32 bytes
+/// * iv_len must be 0 for ECB, in which case iv is not read
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_blocks(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_blocks(data, len, key, iv, iv_len, wrapper, source_location(ram_device), cipher, false).into()
+}
+
+/// Decrypt data with a block cipher mode: AES-ECB or AES-CBC
+///
+/// # Safety:
+/// * same as [encrypt_blocks]
+/// * for CBC, iv must be valid to read for iv_len bytes, which must be 16
+#[no_mangle]
+pub unsafe extern "C" fn decrypt_blocks(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_blocks(data, len, key, iv, iv_len, wrapper, source_location(ram_device), cipher, true).into()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_blocks(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    wrapper: *mut cty::c_void,
+    location: SourceLocation,
+    cipher: Cipher,
+    decrypt: bool,
+) -> Result<(), Status> {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    let cbc = matches!(cipher, Cipher::Aes128Cbc | Cipher::Aes192Cbc | Cipher::Aes256Cbc);
+    match cipher {
+        Cipher::Aes128Cbc | Cipher::Aes128Ecb => {
+            run_aes_blocks::<aes::Aes128>(wrapper, data, key, iv, iv_len, location, cbc, decrypt)
+        }
+        Cipher::Aes192Cbc | Cipher::Aes192Ecb => {
+            run_aes_blocks::<aes::Aes192>(wrapper, data, key, iv, iv_len, location, cbc, decrypt)
+        }
+        Cipher::Aes256Cbc | Cipher::Aes256Ecb => {
+            run_aes_blocks::<aes::Aes256>(wrapper, data, key, iv, iv_len, location, cbc, decrypt)
+        }
+        _ => Err(Status::UnsupportedCipher),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_aes_blocks<B>(
+    wrapper: &mut Wrapper,
+    data: &mut [u8],
+    key: *const u8,
+    iv: *const u8,
+    iv_len: usize,
+    location: SourceLocation,
+    cbc: bool,
+    decrypt: bool,
+) -> Result<(), Status>
+where
+    B: cipher::BlockEncrypt + cipher::BlockDecrypt + cipher::BlockCipher + cipher::KeyInit + Clone,
+{
+    let block_len = <B as cipher::BlockSizeUser>::BlockSize::USIZE;
+    if iv_len != if cbc { block_len } else { 0 } {
+        return Err(Status::InvalidIvLength);
+    }
+    let key = GenericArray::from_slice(core::slice::from_raw_parts(key, B::KeySize::USIZE));
+    match (cbc, decrypt) {
+        (false, false) => wrapper.ecb_encrypt::<B>(data, key, location)?,
+        (false, true) => wrapper.ecb_decrypt::<B>(data, key, location)?,
+        // every block is chained to the previous ciphertext block
+        (true, false) => return Err(Status::UnsupportedCipher),
+        (true, true) => {
+            let iv = GenericArray::from_slice(core::slice::from_raw_parts(iv, iv_len));
+            wrapper.cbc_decrypt::<B>(data, key, iv, location)?
+        }
+    };
+    Ok(())
+}
+
+/// Encrypt data sector by sector with AES-XTS (IEEE 1619), numbering sectors from [first_sector].
+/// A partial final sector is encrypted with ciphertext stealing.
+///
+/// # Safety:
+/// * data must be valid to read / write for len bytes and must be in L2 memory
+/// * key must be valid to read for twice the key size of the cipher, the data key followed by
+///   the tweak key: 32 bytes (64 for AES-256)
+/// * sector_size must divide the L1 buffer length, and every sector must be at least 16 bytes long
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_xts(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    first_sector: u64,
+    sector_size: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_xts(data, len, key, first_sector, sector_size, wrapper, source_location(ram_device), cipher, false).into()
+}
+
+/// Decrypt data encrypted with [encrypt_xts]
+///
+/// # Safety:
+/// * same as [encrypt_xts]
+#[no_mangle]
+pub unsafe extern "C" fn decrypt_xts(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    first_sector: u64,
+    sector_size: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_xts(data, len, key, first_sector, sector_size, wrapper, source_location(ram_device), cipher, true).into()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_xts(
+    data: *mut u8,
+    len: usize,
+    key: *const u8,
+    first_sector: u64,
+    sector_size: usize,
+    wrapper: *mut cty::c_void,
+    location: SourceLocation,
+    cipher: Cipher,
+    decrypt: bool,
+) -> Result<(), Status> {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts_mut(data, len);
+    match cipher {
+        Cipher::Aes128Xts => {
+            run_aes_xts::<aes::Aes128>(wrapper, data, key, first_sector, sector_size, location, decrypt)
+        }
+        Cipher::Aes256Xts => {
+            run_aes_xts::<aes::Aes256>(wrapper, data, key, first_sector, sector_size, location, decrypt)
         }
+        _ => Err(Status::UnsupportedCipher),
+    }
+}
+
+unsafe fn run_aes_xts<B>(
+    wrapper: &mut Wrapper,
+    data: &mut [u8],
+    key: *const u8,
+    first_sector: u64,
+    sector_size: usize,
+    location: SourceLocation,
+    decrypt: bool,
+) -> Result<(), Status>
+where
+    B: cipher::BlockEncrypt
+        + cipher::BlockDecrypt
+        + cipher::BlockCipher
+        + cipher::BlockSizeUser<BlockSize = cipher::consts::U16>
+        + cipher::KeyInit
+        + Clone,
+{
+    let key_len = B::KeySize::USIZE;
+    let key1 = GenericArray::from_slice(core::slice::from_raw_parts(key, key_len));
+    let key2 = GenericArray::from_slice(core::slice::from_raw_parts(key.add(key_len), key_len));
+    if decrypt {
+        wrapper.run_xts::<B, true>(data, key1, key2, first_sector, sector_size, location)?;
+    } else {
+        wrapper.run_xts::<B, false>(data, key1, key2, first_sector, sector_size, location)?;
     }
+    Ok(())
+}
+
+/// Compute the 16 bytes tag of data with a MAC, reading the data without writing it back.
+/// Poly1305 and PMAC are computed by all cores in parallel, CMAC by a single core.
+///
+/// # Safety:
+/// * data must be valid to read for len bytes and must be in L2 memory
+/// * key must be valid to read for the key size of the MAC: 32 bytes for the one-time key of
+///   Poly1305, 16 or 32 bytes for AES
+/// * tag must be valid to write for 16 bytes
+/// * wrapper must be a valid pointer to an initialized PULP Wrapper allocated by this library
+#[no_mangle]
+pub unsafe extern "C" fn compute_mac(
+    data: *const u8,
+    len: usize,
+    key: *const u8,
+    tag: *mut u8,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    cipher: Cipher,
+) -> Status {
+    run_mac(data, len, key, tag, wrapper, source_location(ram_device), cipher).into()
+}
+
+unsafe fn run_mac(
+    data: *const u8,
+    len: usize,
+    key: *const u8,
+    tag: *mut u8,
+    wrapper: *mut cty::c_void,
+    location: SourceLocation,
+    cipher: Cipher,
+) -> Result<(), Status> {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts(data, len);
+    let res = match cipher {
+        Cipher::Poly1305 => wrapper.poly1305(data, &*(key as *const [u8; 32]), location)?,
+        Cipher::Aes128Pmac => wrapper.pmac::<aes::Aes128>(data, mac_key::<aes::Aes128>(key), location)?,
+        Cipher::Aes256Pmac => wrapper.pmac::<aes::Aes256>(data, mac_key::<aes::Aes256>(key), location)?,
+        Cipher::Aes128Cmac => wrapper
+            .mac_serial::<cmac::Cmac<aes::Aes128>>(data, mac_key::<aes::Aes128>(key), location)?
+            .into(),
+        Cipher::Aes256Cmac => wrapper
+            .mac_serial::<cmac::Cmac<aes::Aes256>>(data, mac_key::<aes::Aes256>(key), location)?
+            .into(),
+        _ => return Err(Status::UnsupportedCipher),
+    };
+    core::ptr::copy_nonoverlapping(res.as_ptr(), tag, res.len());
+    Ok(())
+}
+
+unsafe fn mac_key<'a, B: KeySizeUser>(key: *const u8) -> &'a GenericArray<u8, B::KeySize> {
+    GenericArray::from_slice(core::slice::from_raw_parts(key, B::KeySize::USIZE))
+}
+
+fn source_location(ram_device: *mut PiDevice) -> SourceLocation {
+    if let Some(device) = NonNull::new(ram_device) {
+        SourceLocation::Ram(device)
+    } else {
+        SourceLocation::L2
+    }
+}
+
+/// Copy the performance counters of each cluster core during the last operation into [counters]
+///
+/// # Safety:
+/// * counters must be valid to write for CORES elements
+/// * wrapper must be a valid pointer to an initialized PULP wrapper
+#[cfg(feature = "perf-counters")]
+#[no_mangle]
+pub unsafe extern "C" fn cluster_perf_counters(wrapper: *const cty::c_void, counters: *mut pulp_sdk_rust::PerfCounters) {
+    let wrapper = &*(wrapper as *const Wrapper);
+    core::ptr::copy_nonoverlapping(wrapper.perf_counters().as_ptr(), counters, CORES);
+}
+
+/// Misuse a DMA buffer over data, writing in [failed] the misuses which were not reported
+/// (0 if all of them were, see [pulp_wrapper::misuse] for the bits)
+///
+/// # Safety:
+/// * data must be valid to read for len bytes, which must be more than the L1 buffer length
+/// * data must be in RAM if ram_device is given, in L2 otherwise
+/// * failed must be valid to write
+/// * wrapper must be a valid pointer to an initialized PULP wrapper
+#[cfg(feature = "self-test")]
+#[no_mangle]
+pub unsafe extern "C" fn check_dma_misuse(
+    data: *const u8,
+    len: usize,
+    wrapper: *mut cty::c_void,
+    ram_device: *mut PiDevice,
+    failed: *mut u32,
+) -> Status {
+    let wrapper = (wrapper as *mut Wrapper).as_mut().unwrap();
+    let data = core::slice::from_raw_parts(data, len);
+    wrapper
+        .check_dma_misuse(data, source_location(ram_device))
+        .map(|misuses| *failed = misuses)
+        .into()
 }
 
 /// Clean up resources used by the PULP wrapper
@@ -117,7 +1510,7 @@ pub unsafe extern "C" fn encrypt(
 /// Safety: wrapper must be a valid pointer to an initialized PULP wrapper
 #[no_mangle]
 pub unsafe extern "C" fn cluster_close(wrapper: *mut cty::c_void) {
-    let _wrapper = Box::from_raw_in(wrapper as *mut PulpWrapper<CORES,CLUSTER_L1_BUFFER_LEN>, pulp_sdk_rust::L2Allocator);
+    let _wrapper = Box::from_raw_in(wrapper as *mut Wrapper, pulp_sdk_rust::L2Allocator);
 }
 
 /// Encrypt data serially using the unmodified version of this library
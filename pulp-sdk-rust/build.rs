@@ -1,21 +1,70 @@
+use pmsis_layout::LAYOUTS;
+use std::fmt::Write;
 use std::path::Path;
 use std::process::Command;
 const WRAPPER_LIB_DIR: &str = "wrapper/BUILD/PULP/GCC_RISCV/wrapper";
 
 fn main() {
-    Command::new("make")
+    println!("cargo:rerun-if-changed=build.rs");
+    // not the whole directory, the build below writes to it
+    for file in ["wrapper.c", "pmsis_snapshot.h", "Makefile"] {
+        println!("cargo:rerun-if-changed=wrapper/{}", file);
+    }
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR not set");
+    write_layout_asserts(Path::new(&out_dir));
+
+    // the layout asserts are checked by the C compiler, so its failures must fail the build
+    let status = Command::new("make")
         .args(&["clean", "all"])
+        .env("LAYOUT_DIR", &out_dir)
         .current_dir("wrapper")
         .status()
-        .unwrap();
-    Command::new("ar")
+        .expect("unable to run make");
+    assert!(status.success(), "building the C wrapper failed: {}", status);
+    let status = Command::new("ar")
         .args(&["crus", "libwrapper.a", "wrapper.o"])
         .current_dir(&Path::new(WRAPPER_LIB_DIR))
         .status()
-        .unwrap();
+        .expect("unable to run ar");
+    assert!(status.success(), "archiving the C wrapper failed: {}", status);
     let cur_dir = std::env::current_dir()
         .expect("unable to get current dir")
         .join(WRAPPER_LIB_DIR);
     println!("cargo:rustc-link-search={}", cur_dir.display());
     println!("cargo:rustc-link-lib=static=wrapper");
 }
+
+fn write_layout_asserts(out_dir: &Path) {
+    let mut rust = String::new();
+    let mut c = String::from("// Generated by build.rs, do not edit\n#include <stddef.h>\n\n");
+    for layout in LAYOUTS {
+        writeln!(
+            rust,
+            "const _: () = assert!(core::mem::size_of::<{0}>() == {1}, \"{0}: size mismatch\");",
+            layout.rust, layout.size
+        )
+        .unwrap();
+        writeln!(
+            c,
+            "_Static_assert(sizeof({0}) == {1}, \"{0}: size mismatch\");",
+            layout.c, layout.size
+        )
+        .unwrap();
+        for (rust_field, c_field, offset) in layout.fields {
+            writeln!(
+                rust,
+                "const _: () = assert!(offset_of!({0}, {1}) == {2}, \"{0}.{1}: offset mismatch\");",
+                layout.rust, rust_field, offset
+            )
+            .unwrap();
+            writeln!(
+                c,
+                "_Static_assert(offsetof({0}, {1}) == {2}, \"{0}.{1}: offset mismatch\");",
+                layout.c, c_field, offset
+            )
+            .unwrap();
+        }
+    }
+    std::fs::write(out_dir.join("layout.rs"), rust).unwrap();
+    std::fs::write(out_dir.join("layout.h"), c).unwrap();
+}
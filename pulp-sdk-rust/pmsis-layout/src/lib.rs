@@ -0,0 +1,250 @@
+//! Layout of the PMSIS structs mirrored by `pulp_sdk_rust`, shared by its build script, which
+//! generates assertions from it, and by the tests of this crate, which compare it with the
+//! vendored header snapshot on the host.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Expected layout of the PMSIS structs mirrored in `src/bindings/types.rs` (32-bit target).
+///
+/// Every entry is checked three times:
+/// * against the vendored header snapshot, by the tests of this crate (`cargo test -p pmsis-layout`)
+/// * against the Rust definitions, with const assertions included in `types.rs`
+/// * against the real PMSIS headers, with static assertions compiled in `wrapper.c`
+pub struct StructLayout {
+    pub rust: &'static str,
+    pub c: &'static str,
+    pub size: usize,
+    // (rust field, C field, offset)
+    pub fields: &'static [(&'static str, &'static str, usize)],
+}
+
+pub const LAYOUTS: &[StructLayout] = &[
+    StructLayout {
+        rust: "PiClDmaCmd",
+        c: "pi_cl_dma_cmd_t",
+        size: 8,
+        fields: &[("id", "id", 0), ("next", "next", 4)],
+    },
+    StructLayout {
+        rust: "PiDevice",
+        c: "struct pi_device",
+        size: 12,
+        fields: &[("api", "api", 0), ("config", "config", 4), ("data", "data", 8)],
+    },
+    StructLayout {
+        rust: "PiTask",
+        c: "pi_task_t",
+        size: 64,
+        fields: &[
+            ("next", "next", 0),
+            ("arg", "arg", 4),
+            ("done", "done", 20),
+            ("id", "id", 24),
+            ("data", "data", 28),
+        ],
+    },
+    StructLayout {
+        rust: "PiClRamReq",
+        c: "pi_cl_ram_req_t",
+        size: 96,
+        fields: &[
+            ("device", "device", 0),
+            ("addr", "addr", 4),
+            ("ram_addr", "pi_ram_addr", 8),
+            ("size", "size", 12),
+            ("stride", "stride", 16),
+            ("length", "length", 20),
+            ("event", "event", 24),
+            ("next", "next", 88),
+            ("done", "done", 92),
+            ("cid", "cid", 93),
+            ("ext2loc", "ext2loc", 94),
+            ("is_2d", "is_2d", 95),
+        ],
+    },
+    StructLayout {
+        rust: "PiClusterConf",
+        c: "struct pi_cluster_conf",
+        size: 24,
+        fields: &[
+            ("device_type", "device_type", 0),
+            ("id", "id", 4),
+            ("heap_start", "heap_start", 8),
+            ("heap_size", "heap_size", 12),
+            ("event_kernel", "event_kernel", 16),
+            ("flags", "flags", 20),
+        ],
+    },
+    StructLayout {
+        rust: "PiClusterTask",
+        c: "struct pi_cluster_task",
+        size: 40,
+        fields: &[
+            ("entry", "entry", 0),
+            ("arg", "arg", 4),
+            ("stacks", "stacks", 8),
+            ("stack_size", "stack_size", 12),
+            ("slave_stack_size", "slave_stack_size", 16),
+            ("nb_cores", "nb_cores", 20),
+            ("completion_callback", "completion_callback", 24),
+            ("stack_allocated", "stack_allocated", 28),
+            ("next", "next", 32),
+            ("core_mask", "core_mask", 36),
+        ],
+    },
+];
+
+/// Compare [LAYOUTS] with the layout of the structs in [header], returning the differences
+pub fn check_snapshot(header: &str) -> Result<(), String> {
+    let snapshot = parse_header(header);
+    let mut errors = String::new();
+    for layout in LAYOUTS {
+        let name = layout.c.trim_start_matches("struct ");
+        let c = match snapshot.get(name) {
+            Some(c) => c,
+            None => {
+                writeln!(errors, "{}: missing from snapshot", layout.c).unwrap();
+                continue;
+            }
+        };
+        if c.size != layout.size {
+            writeln!(errors, "{}: size {} != {}", layout.c, c.size, layout.size).unwrap();
+        }
+        for (_, field, offset) in layout.fields {
+            match c.fields.get(*field) {
+                Some(o) if o == offset => {}
+                Some(o) => writeln!(errors, "{}.{}: offset {} != {}", layout.c, field, o, offset).unwrap(),
+                None => writeln!(errors, "{}.{}: missing from snapshot", layout.c, field).unwrap(),
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct CLayout {
+    size: usize,
+    align: usize,
+    fields: HashMap<String, usize>,
+}
+
+/// Minimal parser for the struct definitions in the snapshot, computing their layout
+/// with the rules of the ILP32 C ABI used by the PULP toolchain
+fn parse_header(header: &str) -> HashMap<String, CLayout> {
+    let mut defines = HashMap::new();
+    let mut tokens = Vec::new();
+    for line in header.lines() {
+        let line = line.split("//").next().unwrap().trim();
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name.to_string(), value.parse::<usize>().unwrap());
+            }
+            continue;
+        }
+        let mut token = String::new();
+        for ch in line.chars() {
+            if ch.is_alphanumeric() || ch == '_' {
+                token.push(ch);
+            } else {
+                if !token.is_empty() {
+                    tokens.push(core::mem::take(&mut token));
+                }
+                if !ch.is_whitespace() {
+                    tokens.push(ch.to_string());
+                }
+            }
+        }
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+    }
+
+    let mut layouts: HashMap<String, CLayout> = HashMap::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i] != "struct" || tokens.get(i + 2).map(String::as_str) != Some("{") {
+            i += 1;
+            continue;
+        }
+        let tag = tokens[i + 1].clone();
+        i += 3;
+        let mut layout = CLayout {
+            size: 0,
+            align: 1,
+            fields: HashMap::new(),
+        };
+        while tokens[i] != "}" {
+            let end = i + tokens[i..].iter().position(|t| t == ";").unwrap();
+            let decl = &tokens[i..end];
+            let (name, size, align) = field_layout(decl, &defines, &layouts);
+            layout.size = layout.size.next_multiple_of(align);
+            layout.fields.insert(name, layout.size);
+            layout.size += size;
+            layout.align = layout.align.max(align);
+            i = end + 1;
+        }
+        layout.size = layout.size.next_multiple_of(layout.align);
+        // typedef name
+        if tokens.get(i + 2).map(String::as_str) == Some(";") {
+            let typedef = tokens[i + 1].clone();
+            layouts.insert(
+                typedef,
+                CLayout {
+                    size: layout.size,
+                    align: layout.align,
+                    fields: layout.fields.clone(),
+                },
+            );
+        }
+        layouts.insert(tag, layout);
+        i += 1;
+    }
+    layouts
+}
+
+fn field_layout(
+    decl: &[String],
+    defines: &HashMap<String, usize>,
+    layouts: &HashMap<String, CLayout>,
+) -> (String, usize, usize) {
+    let is_ident = |t: &String| t.chars().all(|c| c.is_alphanumeric() || c == '_');
+    // function pointer: ret (*name)(args)
+    if decl.get(1).map(String::as_str) == Some("(") {
+        return (decl[3].clone(), 4, 4);
+    }
+    let (decl, count) = match decl.iter().position(|t| t == "[") {
+        Some(pos) => {
+            let len = &decl[pos + 1];
+            let len = len.parse::<usize>().unwrap_or_else(|_| defines[len]);
+            (&decl[..pos], len)
+        }
+        None => (decl, 1),
+    };
+    let name = decl.last().unwrap().clone();
+    let ty = decl[..decl.len() - 1]
+        .iter()
+        .filter(|t| is_ident(t) && *t != "struct")
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let (size, align) = if decl.iter().any(|t| t == "*") {
+        (4, 4)
+    } else {
+        match ty.as_str() {
+            "char" | "int8_t" | "uint8_t" => (1, 1),
+            "short" | "int16_t" | "uint16_t" => (2, 2),
+            "int" | "unsigned int" | "int32_t" | "uint32_t" | "uintptr_t" => (4, 4),
+            // enums
+            t if t.ends_with("_e") => (4, 4),
+            t => layouts
+                .get(t)
+                .map(|l| (l.size, l.align))
+                .unwrap_or_else(|| panic!("unknown type in PMSIS snapshot: {}", t)),
+        }
+    };
+    (name, size * count, align)
+}
@@ -0,0 +1,20 @@
+use pmsis_layout::check_snapshot;
+
+const SNAPSHOT: &str = include_str!("../../wrapper/pmsis_snapshot.h");
+
+#[test]
+fn layouts_match_snapshot() {
+    if let Err(errors) = check_snapshot(SNAPSHOT) {
+        panic!("PMSIS layout drift against the vendored snapshot:\n{}", errors);
+    }
+}
+
+#[test]
+fn drift_is_reported() {
+    // an extra field moves everything after it
+    let drifted = SNAPSHOT.replacen("void *heap_start;", "void *heap_start;\n    uint32_t padding;", 1);
+    assert_ne!(drifted, SNAPSHOT);
+    let errors = check_snapshot(&drifted).unwrap_err();
+    assert!(errors.contains("struct pi_cluster_conf: size 28 != 24"), "{}", errors);
+    assert!(errors.contains("struct pi_cluster_conf.heap_size: offset 16 != 12"), "{}", errors);
+}
@@ -1,9 +1,9 @@
-use crate::{pi_cl_l1_free, pi_cl_l1_malloc, pi_l2_free, pi_l2_malloc, PiDevice};
+use crate::{pi_cl_l1_free, pi_cl_l1_malloc, pi_l2_free, pi_l2_malloc, PiDevice, Platform, Target};
 use core::alloc::{AllocError, Allocator, GlobalAlloc, Layout};
 use core::ptr::NonNull;
 
-const L2_ALIGN: usize = 4;
-const CLUSTER_L1_ALIGN: usize = 4;
+const L2_ALIGN: usize = <Target as Platform>::L2_ALIGN;
+const CLUSTER_L1_ALIGN: usize = <Target as Platform>::L1_ALIGN;
 
 /// Allocate memory on chip L2 memory
 /// Wrapper around `pi_l2_malloc` and `pi_l2_free`
@@ -1,6 +1,8 @@
 mod types;
 pub use types::*;
 
+use crate::{Platform, Target};
+
 extern "C" {
     pub fn pi_cl_dma_cmd_wrap(
         ext: cty::uint32_t,
@@ -34,6 +36,16 @@ extern "C" {
 
     pub fn abort_all();
 
+    pub fn pi_cluster_id_wrap() -> cty::uint32_t;
+
+    pub fn pi_is_fc_wrap() -> cty::c_int;
+
+    pub fn pi_cl_team_nb_cores_wrap() -> cty::c_int;
+
+    pub fn pi_cl_l1_size_wrap() -> cty::uint32_t;
+
+    pub fn pi_cl_l1_free_size_wrap() -> cty::uint32_t;
+
     pub fn pi_cl_team_fork_wrap(
         num_cores: usize,
         cluster_fn: extern "C" fn(*mut cty::c_void),
@@ -42,6 +54,10 @@ extern "C" {
 
     pub fn pi_cl_team_barrier_wrap();
 
+    pub fn pi_cl_team_critical_enter_wrap();
+
+    pub fn pi_cl_team_critical_exit_wrap();
+
     pub fn pi_l2_malloc(size: cty::c_int) -> *mut cty::c_void;
 
     pub fn pi_l2_free(chunk: *mut cty::c_void, size: cty::c_int);
@@ -67,6 +83,30 @@ extern "C" {
     ) -> *mut PiClusterTask;
 
     pub fn pi_cluster_send_task_to_cl(device: *mut PiDevice, task: *mut PiClusterTask) -> cty::c_int;
+
+    pub fn pi_cluster_send_task_to_cl_async(
+        device: *mut PiDevice,
+        task: *mut PiClusterTask,
+        event: *mut PiTask,
+    ) -> cty::c_int;
+
+    pub fn pi_task_block_wrap(task: *mut PiTask) -> *mut PiTask;
+
+    pub fn pi_task_wait_on_wrap(task: *mut PiTask);
+
+    pub fn eu_evt_mask_set_wrap(mask: cty::uint32_t);
+
+    pub fn eu_evt_mask_clr_wrap(mask: cty::uint32_t);
+
+    pub fn eu_evt_mask_wait_and_clr_wrap(mask: cty::uint32_t) -> cty::uint32_t;
+
+    pub fn eu_evt_clr_wrap(mask: cty::uint32_t);
+
+    pub fn eu_evt_trig_sw_wrap(event: cty::uint32_t, core_mask: cty::uint32_t);
+
+    pub fn pi_perf_cl_start_wrap();
+
+    pub fn pi_perf_cl_stop_wrap(counters: *mut cty::uint32_t);
 }
 
 pub unsafe fn pi_cluster_task(task: *mut PiClusterTask,
@@ -79,8 +119,59 @@ pub unsafe fn pi_cluster_task(task: *mut PiClusterTask,
 #[inline(always)]
 pub unsafe fn pi_core_id() -> usize {
     let core_id: usize;
-    core::arch::asm!("csrr {core_id}, 0x014", core_id = out(reg) core_id,);
-    core_id & 0x01f
+    core::arch::asm!(
+        "csrr {core_id}, {csr}",
+        core_id = out(reg) core_id,
+        csr = const <Target as Platform>::CORE_ID_CSR,
+    );
+    core_id & <Target as Platform>::CORE_ID_MASK
+}
+
+/// Initialize [task] as an event the FC can block on
+///
+/// # Safety:
+/// * task must be valid to write and must not move until the event is triggered
+pub unsafe fn pi_task_block(task: *mut PiTask) -> *mut PiTask {
+    pi_task_block_wrap(task)
+}
+
+/// Block the FC until [task] is triggered
+///
+/// # Safety:
+/// * task must have been initialized by [pi_task_block]
+pub unsafe fn pi_task_wait_on(task: *mut PiTask) {
+    pi_task_wait_on_wrap(task)
+}
+
+/// Id of the cluster the calling core belongs to
+pub fn pi_cluster_id() -> usize {
+    unsafe { pi_cluster_id_wrap() as usize }
+}
+
+/// Whether the caller is running on the fabric controller
+pub fn pi_is_fc() -> bool {
+    unsafe { pi_is_fc_wrap() != 0 }
+}
+
+/// Number of cores involved in the current team (i.e. the last `pi_cl_team_fork`)
+///
+/// # Safety:
+/// * must be called from within a PULP cluster
+pub unsafe fn pi_cl_team_nb_cores() -> usize {
+    pi_cl_team_nb_cores_wrap() as usize
+}
+
+/// Total size of the cluster L1 memory, in bytes
+pub fn pi_cl_l1_size() -> usize {
+    unsafe { pi_cl_l1_size_wrap() as usize }
+}
+
+/// Free memory left in the cluster L1 heap, in bytes
+///
+/// # Safety:
+/// * the cluster must have been opened
+pub unsafe fn pi_cl_l1_free_size() -> usize {
+    pi_cl_l1_free_size_wrap() as usize
 }
 
 pub unsafe fn pi_cl_dma_cmd(
@@ -147,6 +238,16 @@ pub fn pi_cl_team_barrier() {
     unsafe { pi_cl_team_barrier_wrap() }
 }
 
+/// Enter the critical section of the team, waiting until no other core is in it
+pub fn pi_cl_team_critical_enter() {
+    unsafe { pi_cl_team_critical_enter_wrap() }
+}
+
+/// Leave the critical section entered with [pi_cl_team_critical_enter]
+pub fn pi_cl_team_critical_exit() {
+    unsafe { pi_cl_team_critical_exit_wrap() }
+}
+
 // TODO: compiler fence?
 // TODO: rewrite this as a safe function
 pub unsafe fn pi_cl_team_fork(
@@ -156,3 +257,29 @@ pub unsafe fn pi_cl_team_fork(
 ) {
     pi_cl_team_fork_wrap(num_cores, cluster_fn, args);
 }
+
+/// Enable the events in [mask] for the calling core
+pub fn eu_evt_mask_set(mask: u32) {
+    unsafe { eu_evt_mask_set_wrap(mask) }
+}
+
+/// Disable the events in [mask] for the calling core
+pub fn eu_evt_mask_clr(mask: u32) {
+    unsafe { eu_evt_mask_clr_wrap(mask) }
+}
+
+/// Put the calling core to sleep (clock gated) until one of the events in [mask]
+/// is received, then clear them from the buffer. Returns the events that woke up the core.
+pub fn eu_evt_mask_wait_and_clr(mask: u32) -> u32 {
+    unsafe { eu_evt_mask_wait_and_clr_wrap(mask) }
+}
+
+/// Clear the events in [mask] from the event buffer of the calling core
+pub fn eu_evt_clr(mask: u32) {
+    unsafe { eu_evt_clr_wrap(mask) }
+}
+
+/// Trigger software event [event] on all cores in [core_mask]
+pub fn eu_evt_trig_sw(event: u32, core_mask: u32) {
+    unsafe { eu_evt_trig_sw_wrap(event, core_mask) }
+}
@@ -41,6 +41,11 @@ impl PiDevice {
             data: core::ptr::null_mut() as *mut cty::c_void,
         }
     }
+
+    /// Whether the device has been opened, i.e. its driver api is set
+    pub fn is_open(&self) -> bool {
+        !self.api.is_null()
+    }
 }
 
 #[repr(C)]
@@ -107,7 +112,7 @@ pub struct PiTask {
 }
 
 impl PiTask {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             next: core::ptr::null_mut(),
             arg: [0; 4],
@@ -117,6 +122,16 @@ impl PiTask {
             implem: PiTaskImplem::default(),
         }
     }
+    /// Whether the event was triggered, e.g. by the completion of a request
+    pub fn is_done(&self) -> bool {
+        unsafe { core::ptr::read_volatile(&self.done) != 0 }
+    }
+}
+
+impl Default for PiTask {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[repr(C)]
@@ -205,8 +220,18 @@ impl PiClusterTask {
     }
 
     pub fn set_stack_size(&mut self, size: usize) {
-        self.stack_size = size as u32;
-        self.slave_stack_size = size as u32;
+        self.set_stack_sizes(size, size);
+    }
+
+    pub fn set_stack_sizes(&mut self, master: usize, slave: usize) {
+        self.stack_size = master as u32;
+        self.slave_stack_size = slave as u32;
+    }
+
+    /// Use the provided memory for the cores stacks instead of letting the runtime allocate them.
+    /// Must be valid for `stack_size + slave_stack_size * (nb_cores - 1)` bytes.
+    pub fn set_stacks(&mut self, stacks: *mut u8) {
+        self.stacks = stacks as *mut cty::c_void;
     }
 }
 
@@ -236,3 +261,14 @@ pub struct PiTaskOpaque {
     // Do not let the compiler assume stuff it shouldn't
     _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
 }
+
+// Layout checks generated by build.rs from the table in `pmsis-layout`
+macro_rules! offset_of {
+    ($ty:ty, $field:ident) => {{
+        let uninit = core::mem::MaybeUninit::<$ty>::uninit();
+        let base = uninit.as_ptr();
+        unsafe { (core::ptr::addr_of!((*base).$field) as *const u8).offset_from(base as *const u8) as usize }
+    }};
+}
+
+include!(concat!(env!("OUT_DIR"), "/layout.rs"));
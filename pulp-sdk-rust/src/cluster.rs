@@ -1,18 +1,29 @@
+use core::alloc::AllocError;
+use core::marker::PhantomData;
 use core_alloc::boxed::Box;
 use crate::*;
 
-const DEFAULT_STACK_SIZE: usize = 2048;
-
 /// Using raw pointers means we can guarantee at the same time:
 /// * no special aliasing since the returned pointer will be used by the C code in ways we cannot predict
 /// * pinning
 pub struct Cluster<const CORES: usize> {
     device: *mut PiDevice,
     _conf: *mut PiClusterConf,
+    // of the last blocking task, see [execute_fn_parallel_async]
+    #[cfg(feature = "stack-check")]
+    stack_usage: [StackUsage; CORES],
+    #[cfg(feature = "perf-counters")]
+    perf_counters: [PerfCounters; CORES],
 }
 
 impl<const CORES: usize> Cluster<CORES> {
     pub fn new() -> Result<Self, ()> {
+        // the platform features must match the chip the SDK was built for
+        assert_eq!(
+            pi_cl_l1_size(),
+            <Target as Platform>::L1_SIZE,
+            "L1 size of the selected platform does not match the SDK"
+        );
         let device: *mut _ = Box::leak(Box::new_in(PiDevice::uninit(), L2Allocator));
         let _conf: *mut _ = Box::leak(Box::new_in(PiClusterConf::uninit(), L2Allocator));
 
@@ -23,7 +34,14 @@ impl<const CORES: usize> Cluster<CORES> {
                 return Err(());
             }
 
-            Ok(Self { device, _conf })
+            Ok(Self {
+                device,
+                _conf,
+                #[cfg(feature = "stack-check")]
+                stack_usage: [StackUsage::default(); CORES],
+                #[cfg(feature = "perf-counters")]
+                perf_counters: [PerfCounters::default(); CORES],
+            })
         }
     }
 
@@ -32,32 +50,156 @@ impl<const CORES: usize> Cluster<CORES> {
         ClusterAllocator::new(self.device)
     }
 
+    /// Free memory left in the cluster L1 heap, in bytes
+    pub fn l1_free(&self) -> usize {
+        unsafe { pi_cl_l1_free_size() }
+    }
+
+    /// L1 memory taken by [execute_fn_parallel_with_stacks] for the cores stacks and the argument block
+    pub const fn execute_l1_overhead<T>(stacks: StackSize) -> usize {
+        stacks.with_guard().total(CORES) + core::mem::size_of::<ExecFn<T, CORES>>()
+    }
+
     /// Schedule a function for execution on each cluster core.
     /// This is a blocking function.
-    pub fn execute_fn_parallel<T: Send + Sync>(&mut self, f: extern "C" fn(&T), args: T) {
-        let mut cluster_task = PiClusterTask::uninit();
-        let allocator = self.l1_allocator();
-        let exec_fn_args = Box::leak(Box::new_in(ExecFn{f, args} , allocator));
-        unsafe {
-            pi_cluster_task(
-                &mut cluster_task,
-                Self::execute_inner_pre_fork::<CORES, T>,
-                exec_fn_args as *mut _ as *mut cty::c_void,
-            );
-            cluster_task.set_stack_size(DEFAULT_STACK_SIZE);
-            pi_cluster_send_task_to_cl(self.device, &mut cluster_task);
-            let _ = Box::from_raw_in(exec_fn_args, allocator);
+    ///
+    /// Fails without running [f] if the task does not fit in the free L1 memory.
+    pub fn execute_fn_parallel<T: Send + Sync>(&mut self, f: extern "C" fn(&T), args: T) -> Result<(), AllocError> {
+        self.execute_fn_parallel_with_stacks(f, args, StackSize::DEFAULT)
+    }
+
+    /// Schedule a function for execution on each cluster core, with custom stack sizes
+    /// for the master and slave cores.
+    /// This is a blocking function.
+    ///
+    /// With the `stack-check` feature, stacks and a guard region below each of them are filled
+    /// with a canary pattern before the fork, and the usage of each core can be retrieved
+    /// afterwards with [stack_usage].
+    /// With the `perf-counters` feature, the performance counters of each core while running [f]
+    /// can be retrieved afterwards with [perf_counters].
+    ///
+    /// Fails without running [f] if the task does not fit in the free L1 memory.
+    pub fn execute_fn_parallel_with_stacks<T: Send + Sync>(
+        &mut self,
+        f: extern "C" fn(&T),
+        args: T,
+        stacks: StackSize,
+    ) -> Result<(), AllocError> {
+        // Safety: the job is not leaked
+        let stats = unsafe { self.execute_fn_parallel_async(f, args, stacks) }?.finish();
+        if let Some(_stats) = stats {
+            #[cfg(feature = "stack-check")]
+            {
+                self.stack_usage = _stats.stack_usage;
+            }
+            #[cfg(feature = "perf-counters")]
+            {
+                self.perf_counters = _stats.perf_counters;
+            }
         }
+        Ok(())
+    }
+
+    /// Same as [execute_fn_parallel_with_stacks], but returns as soon as the task is queued.
+    ///
+    /// Tasks run one after the other in the order they were queued, so the FC can prepare
+    /// the arguments of the next one while the cluster is busy.
+    /// [stack_usage] and [perf_counters] are not updated, as the job completes while they
+    /// may be borrowed.
+    ///
+    /// # Safety:
+    /// * the returned job must not be leaked (e.g. with `core::mem::forget`), as [args] may
+    ///   borrow data that the cores keep using until the task completes
+    pub unsafe fn execute_fn_parallel_async<T: Send + Sync>(
+        &self,
+        f: extern "C" fn(&T),
+        args: T,
+        stacks: StackSize,
+    ) -> Result<ClusterJob<'_, T, CORES>, AllocError> {
+        let allocator = self.l1_allocator();
+        let stacks = stacks.with_guard();
+        let exec_fn: *mut _ = Box::leak(Box::try_new_in(
+            ExecFn::<T, CORES> {
+                f,
+                args,
+                task: PiClusterTask::uninit(),
+                event: PiTask::new(),
+                #[cfg(feature = "perf-counters")]
+                perf_counters: [PerfCounters::default(); CORES],
+            },
+            allocator,
+        )?);
+        let task = core::ptr::addr_of_mut!((*exec_fn).task);
+        pi_cluster_task(task, Self::execute_inner_pre_fork::<CORES, T>, exec_fn as *mut cty::c_void);
+        (*task).set_stack_sizes(stacks.master, stacks.slave);
+
+        // Provide the stacks ourselves so that we can inspect them after the task has completed
+        #[cfg(feature = "stack-check")]
+        let stacks_mem = {
+            let mem = match Box::<[u32], _>::try_new_uninit_slice_in(stacks.total(CORES) / 4, allocator) {
+                Ok(mem) => Box::leak(mem),
+                Err(e) => {
+                    let _ = Box::from_raw_in(exec_fn, allocator);
+                    return Err(e);
+                }
+            };
+            let ptr = mem.as_mut_ptr() as *mut u8;
+            canary::paint(ptr, stacks, CORES);
+            (*task).set_stacks(ptr);
+            ptr
+        };
+
+        let event = core::ptr::addr_of_mut!((*exec_fn).event);
+        pi_task_block(event);
+        pi_cluster_send_task_to_cl_async(self.device, task, event);
+
+        Ok(ClusterJob {
+            cluster: self,
+            exec_fn,
+            #[cfg(feature = "stack-check")]
+            stacks,
+            #[cfg(feature = "stack-check")]
+            stacks_mem,
+            _args: PhantomData,
+        })
+    }
+
+    /// Stack usage of each core during the last blocking task
+    #[cfg(feature = "stack-check")]
+    pub fn stack_usage(&self) -> &[StackUsage; CORES] {
+        &self.stack_usage
+    }
+
+    /// Whether any core overflowed its stack during the last blocking task
+    #[cfg(feature = "stack-check")]
+    pub fn stack_overflow(&self) -> bool {
+        self.stack_usage.iter().any(|usage| usage.overflow)
+    }
+
+    /// Performance counters of each core during the last blocking task
+    #[cfg(feature = "perf-counters")]
+    pub fn perf_counters(&self) -> &[PerfCounters; CORES] {
+        &self.perf_counters
     }
 
     extern "C" fn execute_inner_pre_fork<const C: usize, T: Send + Sync>(data: *mut cty::c_void) {
-        unsafe { pi_cl_team_fork(C, Self::execute_inner::<T>, data) }
+        unsafe { pi_cl_team_fork(C, Self::execute_inner::<C, T>, data) }
     }
 
-    extern "C" fn execute_inner<T: Send + Sync>(data: *mut cty::c_void) {
+    extern "C" fn execute_inner<const C: usize, T: Send + Sync>(data: *mut cty::c_void) {
+        let exec_fn = data as *mut ExecFn<T, C>;
         // Safety: we did the allocation ourself, all is good
-        let ExecFn { ref f, ref args } = unsafe { &*(data as *mut ExecFn<T>) };
-        f(args)
+        let (f, args) = unsafe { ((*exec_fn).f, &(*exec_fn).args) };
+        #[cfg(feature = "perf-counters")]
+        unsafe {
+            PerfCounters::start()
+        };
+        f(args);
+        // Each core only writes its own slot
+        #[cfg(feature = "perf-counters")]
+        unsafe {
+            (*exec_fn).perf_counters[pi_core_id()] = PerfCounters::stop();
+        }
     }
 }
 
@@ -67,7 +209,90 @@ impl<const CORES:usize> Drop for Cluster<CORES> {
     }
 }
 
-struct ExecFn<T> {
+/// A task queued on the cluster by [Cluster::execute_fn_parallel_async].
+///
+/// Dropping the job blocks until the task has completed.
+pub struct ClusterJob<'a, T, const CORES: usize> {
+    cluster: &'a Cluster<CORES>,
+    // null once the task has completed and its memory was released
+    exec_fn: *mut ExecFn<T, CORES>,
+    #[cfg(feature = "stack-check")]
+    stacks: StackSize,
+    #[cfg(feature = "stack-check")]
+    stacks_mem: *mut u8,
+    _args: PhantomData<T>,
+}
+
+impl<'a, T, const CORES: usize> ClusterJob<'a, T, CORES> {
+    /// Whether the task has completed, without blocking
+    pub fn is_done(&self) -> bool {
+        self.exec_fn.is_null() || unsafe { (*self.exec_fn).event.is_done() }
+    }
+
+    /// Block until the task has completed
+    pub fn wait(mut self) {
+        self.complete();
+    }
+
+    /// Same as [wait], returning what the task left for [Cluster] to report
+    fn finish(mut self) -> Option<TaskStats<CORES>> {
+        self.complete()
+    }
+
+    /// Wait for the task and release its memory, the first time only
+    fn complete(&mut self) -> Option<TaskStats<CORES>> {
+        if self.exec_fn.is_null() {
+            return None;
+        }
+        let allocator = self.cluster.l1_allocator();
+        unsafe {
+            pi_task_wait_on(core::ptr::addr_of_mut!((*self.exec_fn).event));
+
+            #[cfg(feature = "perf-counters")]
+            let perf_counters = (*self.exec_fn).perf_counters;
+            let _ = Box::from_raw_in(self.exec_fn, allocator);
+            self.exec_fn = core::ptr::null_mut();
+
+            #[cfg(feature = "stack-check")]
+            let stack_usage = {
+                let (stacks, stacks_mem) = (self.stacks, self.stacks_mem);
+                let usage = core::array::from_fn(|core_id| canary::inspect(stacks_mem, stacks, core_id));
+                let _ = Box::from_raw_in(
+                    core::slice::from_raw_parts_mut(stacks_mem as *mut u32, stacks.total(CORES) / 4),
+                    allocator,
+                );
+                usage
+            };
+            Some(TaskStats {
+                #[cfg(feature = "stack-check")]
+                stack_usage,
+                #[cfg(feature = "perf-counters")]
+                perf_counters,
+            })
+        }
+    }
+}
+
+impl<'a, T, const CORES: usize> Drop for ClusterJob<'a, T, CORES> {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
+
+/// What a completed task leaves for [Cluster::stack_usage] and [Cluster::perf_counters]
+struct TaskStats<const CORES: usize> {
+    #[cfg(feature = "stack-check")]
+    stack_usage: [StackUsage; CORES],
+    #[cfg(feature = "perf-counters")]
+    perf_counters: [PerfCounters; CORES],
+}
+
+struct ExecFn<T, const CORES: usize> {
     f: extern "C" fn(&T),
     args: T,
+    // the runtime keeps pointers to these until the task completes
+    task: PiClusterTask,
+    event: PiTask,
+    #[cfg(feature = "perf-counters")]
+    perf_counters: [PerfCounters; CORES],
 }
@@ -0,0 +1,118 @@
+use crate::*;
+
+/// Number of software events available in the cluster event unit
+pub const SW_EVENTS: u8 = 8;
+
+/// A set of cluster cores, one bit per core id
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CoreMask(u32);
+
+impl CoreMask {
+    /// No cores
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// The first [CORES] cores of the cluster
+    pub const fn all<const CORES: usize>() -> Self {
+        Self(((1u64 << CORES) - 1) as u32)
+    }
+
+    /// Only core [core_id]
+    pub const fn core(core_id: usize) -> Self {
+        Self(1 << core_id)
+    }
+
+    pub const fn with(self, core_id: usize) -> Self {
+        Self(self.0 | (1 << core_id))
+    }
+
+    pub const fn without(self, core_id: usize) -> Self {
+        Self(self.0 & !(1 << core_id))
+    }
+
+    pub const fn contains(&self, core_id: usize) -> bool {
+        self.0 & (1 << core_id) != 0
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+}
+
+/// A software event of the cluster event unit.
+///
+/// A core waiting for an event is clock gated until the event is triggered, which
+/// makes events a cheap way to synchronize a subset of the cluster without going
+/// through a full [pi_cl_team_barrier], e.g. a producer / consumer pipeline where
+/// one core drives the DMA and the others compute:
+///
+/// ```ignore
+/// let ready = Event::new(3).unwrap();
+/// let done = Event::new(4).unwrap();
+/// if pi_core_id() == 0 {
+///     // .. wait for dma
+///     ready.trigger(CoreMask::all::<CORES>().without(0));
+///     done.wait();
+/// } else {
+///     ready.wait();
+///     // .. compute
+/// }
+/// ```
+///
+/// Events are not counted: triggering an event which is already pending on a core
+/// has no additional effect. To wait for several cores, give each one a different
+/// event and use [wait_all].
+///
+/// Some software events might be used internally by the runtime, it's up to the
+/// caller to pick ids that are free on the target platform.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Event {
+    id: u8,
+}
+
+impl Event {
+    /// Returns `None` if [id] is not a valid software event
+    pub const fn new(id: u8) -> Option<Self> {
+        if id < SW_EVENTS {
+            Some(Self { id })
+        } else {
+            None
+        }
+    }
+
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Bit of this event in the event unit masks
+    pub const fn mask(&self) -> u32 {
+        1 << self.id
+    }
+
+    /// Send this event to every core in [cores]
+    #[inline(always)]
+    pub fn trigger(&self, cores: CoreMask) {
+        eu_evt_trig_sw(self.id as u32, cores.bits());
+    }
+
+    /// Sleep until this event is received and consume it
+    #[inline(always)]
+    pub fn wait(&self) {
+        eu_evt_mask_wait_and_clr(self.mask());
+    }
+
+    /// Drop this event if it's pending on the calling core
+    #[inline(always)]
+    pub fn clear(&self) {
+        eu_evt_clr(self.mask());
+    }
+}
+
+/// Sleep until all events in [events] have been received, in any order
+pub fn wait_all(events: &[Event]) {
+    let mut pending = events.iter().fold(0, |mask, e| mask | e.mask());
+    while pending != 0 {
+        pending &= !eu_evt_mask_wait_and_clr(pending);
+    }
+}
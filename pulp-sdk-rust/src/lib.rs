@@ -2,6 +2,7 @@
 #![feature(allocator_api)]
 #![feature(alloc_error_handler)]
 #![feature(nonnull_slice_from_raw_parts)]
+#![feature(asm_const)]
 extern crate alloc as core_alloc;
 
 // Should use a more specific target triple like riscv32imcXpulp-unknown-pulp-{abi}
@@ -12,7 +13,15 @@ compile_error!("unsupported target");
 mod alloc;
 mod bindings;
 mod cluster;
+mod event;
+mod perf;
+mod platform;
+mod stack;
 
 pub use alloc::*;
 pub use bindings::*;
 pub use cluster::*;
+pub use event::*;
+pub use perf::*;
+pub use platform::*;
+pub use stack::*;
@@ -0,0 +1,38 @@
+use crate::*;
+
+/// Performance counters of a single cluster core over a task
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PerfCounters {
+    pub cycles: u32,
+    pub instructions: u32,
+    /// Cycles stalled because of a conflict on a TCDM (L1) bank
+    pub tcdm_contention: u32,
+    pub load_stalls: u32,
+}
+
+impl PerfCounters {
+    /// Reset and start the counters of the calling core
+    ///
+    /// # Safety:
+    /// * must be called from within a PULP cluster
+    pub unsafe fn start() {
+        pi_perf_cl_start_wrap();
+    }
+
+    /// Stop the counters of the calling core and read them
+    ///
+    /// # Safety:
+    /// * must be called from within a PULP cluster, after [start](Self::start)
+    pub unsafe fn stop() -> Self {
+        let mut counters = [0u32; 4];
+        pi_perf_cl_stop_wrap(counters.as_mut_ptr());
+        let [cycles, instructions, tcdm_contention, load_stalls] = counters;
+        Self {
+            cycles,
+            instructions,
+            tcdm_contention,
+            load_stalls,
+        }
+    }
+}
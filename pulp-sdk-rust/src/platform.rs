@@ -0,0 +1,81 @@
+/// Per-chip constants of a PULP platform.
+///
+/// The platform used by this crate is selected at compile time with one of the `gap8`
+/// (default), `gap9` or `pulp-open` features and is available as [Target].
+pub trait Platform {
+    /// Number of cluster cores available to run parallel work
+    const CLUSTER_CORES: usize;
+    /// Size of the cluster L1 (TCDM) memory, in bytes, checked against the SDK by [Cluster::new]
+    const L1_SIZE: usize;
+    /// Number of word-interleaved banks of the cluster L1 memory
+    const L1_BANKS: usize;
+    /// Maximum alignment supported by the L1 allocator
+    const L1_ALIGN: usize;
+    /// Maximum alignment supported by the L2 allocator
+    const L2_ALIGN: usize;
+    /// Alignment required to get full bandwidth out of the cluster DMA
+    const DMA_ALIGN: usize;
+    /// CSR holding the core and cluster id
+    const CORE_ID_CSR: u16;
+    /// Mask of the core id in [CORE_ID_CSR]
+    const CORE_ID_MASK: usize;
+    /// Default size of each of the buffers used to stream data through L1,
+    /// leaving enough room for stacks and runtime data
+    const DEFAULT_L1_BUFFER_LEN: usize;
+}
+
+pub struct Gap8;
+
+impl Platform for Gap8 {
+    const CLUSTER_CORES: usize = 8;
+    const L1_SIZE: usize = 64 * 1024;
+    const L1_BANKS: usize = 16;
+    const L1_ALIGN: usize = 4;
+    const L2_ALIGN: usize = 4;
+    const DMA_ALIGN: usize = 4;
+    const CORE_ID_CSR: u16 = 0x014;
+    const CORE_ID_MASK: usize = 0x01f;
+    const DEFAULT_L1_BUFFER_LEN: usize = 8192;
+}
+
+pub struct Gap9;
+
+impl Platform for Gap9 {
+    // The 9th core is the cluster controller, which is not used for parallel work
+    const CLUSTER_CORES: usize = 8;
+    const L1_SIZE: usize = 128 * 1024;
+    const L1_BANKS: usize = 16;
+    const L1_ALIGN: usize = 4;
+    const L2_ALIGN: usize = 4;
+    const DMA_ALIGN: usize = 4;
+    const CORE_ID_CSR: u16 = 0xf14;
+    const CORE_ID_MASK: usize = 0x01f;
+    const DEFAULT_L1_BUFFER_LEN: usize = 16384;
+}
+
+pub struct PulpOpen;
+
+impl Platform for PulpOpen {
+    const CLUSTER_CORES: usize = 8;
+    const L1_SIZE: usize = 64 * 1024;
+    const L1_BANKS: usize = 16;
+    const L1_ALIGN: usize = 4;
+    const L2_ALIGN: usize = 4;
+    const DMA_ALIGN: usize = 4;
+    const CORE_ID_CSR: u16 = 0xf14;
+    const CORE_ID_MASK: usize = 0x01f;
+    const DEFAULT_L1_BUFFER_LEN: usize = 8192;
+}
+
+#[cfg(all(feature = "gap9", feature = "pulp-open"))]
+compile_error!("only one platform feature can be enabled");
+#[cfg(all(feature = "gap8", any(feature = "gap9", feature = "pulp-open")))]
+compile_error!("only one platform feature can be enabled");
+
+/// The platform selected at compile time
+#[cfg(feature = "gap9")]
+pub type Target = Gap9;
+#[cfg(feature = "pulp-open")]
+pub type Target = PulpOpen;
+#[cfg(not(any(feature = "gap9", feature = "pulp-open")))]
+pub type Target = Gap8;
@@ -0,0 +1,103 @@
+/// Stack sizes for the cores running a cluster task, in bytes
+///
+/// The master core (core 0) also runs the task entry and the fork, so it usually
+/// needs a bigger stack than the slave cores.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StackSize {
+    pub master: usize,
+    pub slave: usize,
+}
+
+impl StackSize {
+    pub const DEFAULT: Self = Self::uniform(2048);
+
+    pub const fn new(master: usize, slave: usize) -> Self {
+        Self { master, slave }
+    }
+
+    /// Same size for master and slave cores
+    pub const fn uniform(size: usize) -> Self {
+        Self::new(size, size)
+    }
+
+    /// Total L1 memory needed for the stacks of [cores] cores
+    pub const fn total(&self, cores: usize) -> usize {
+        self.master + self.slave * (cores - 1)
+    }
+
+    /// Stack sizes given to the runtime, with room below each stack for the guard region
+    /// painted by the `stack-check` feature
+    pub(crate) const fn with_guard(&self) -> Self {
+        Self::new(self.master + STACK_GUARD, self.slave + STACK_GUARD)
+    }
+
+    /// Offset and size of the stack of [core_id] in the stacks allocation.
+    ///
+    /// The runtime lays out stacks contiguously starting with the master one,
+    /// and each stack grows downwards from the end of its region.
+    pub const fn region(&self, core_id: usize) -> (usize, usize) {
+        if core_id == 0 {
+            (0, self.master)
+        } else {
+            (self.master + self.slave * (core_id - 1), self.slave)
+        }
+    }
+}
+
+impl Default for StackSize {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Bytes at the bottom of each stack that a core should never reach, checked for writes
+/// after each task with the `stack-check` feature
+pub(crate) const STACK_GUARD: usize = if cfg!(feature = "stack-check") { 64 } else { 0 };
+
+/// Stack usage of a core during the last cluster task
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct StackUsage {
+    /// Size of the stack, in bytes
+    pub size: usize,
+    /// Maximum number of bytes used
+    pub high_water: usize,
+    /// The guard region below the stack was overwritten: the core has written
+    /// past the end of its stack, into memory that may belong to another core
+    pub overflow: bool,
+}
+
+#[cfg(feature = "stack-check")]
+pub(crate) mod canary {
+    use super::*;
+
+    const CANARY: u32 = 0xDEAD_BEEF;
+
+    /// Fill the stacks of all cores with the canary pattern
+    ///
+    /// # Safety:
+    /// * stacks must be valid to write for stacks.total(cores) bytes and 4-byte aligned
+    pub(crate) unsafe fn paint(stacks: *mut u8, size: StackSize, cores: usize) {
+        let words = stacks as *mut u32;
+        for i in 0..size.total(cores) / 4 {
+            words.add(i).write_volatile(CANARY);
+        }
+    }
+
+    /// Check how much of the stack of [core_id] was overwritten since [paint], [size] including
+    /// the guard region at the bottom of each stack (see [StackSize::with_guard])
+    ///
+    /// # Safety:
+    /// * same as [paint]
+    pub(crate) unsafe fn inspect(stacks: *mut u8, size: StackSize, core_id: usize) -> StackUsage {
+        let (offset, len) = size.region(core_id);
+        let words = stacks.add(offset) as *const u32;
+        let guard = STACK_GUARD / 4;
+        let intact = |i: &usize| words.add(*i).read_volatile() == CANARY;
+        let untouched = (guard..len / 4).take_while(intact).count();
+        StackUsage {
+            size: len - STACK_GUARD,
+            high_water: len - STACK_GUARD - untouched * 4,
+            overflow: !(0..guard).all(|i| intact(&i)),
+        }
+    }
+}
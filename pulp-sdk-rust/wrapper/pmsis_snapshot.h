@@ -0,0 +1,72 @@
+// Snapshot of the PMSIS structures that are mirrored in `src/bindings/types.rs`.
+//
+// This is not compiled, it's parsed by the tests of `pmsis-layout` (`cargo test -p pmsis-layout`)
+// to check that the expected layout of the Rust definitions still agrees with the headers it
+// was written against.
+// When updating the SDK, refresh this file and the layout table in `pmsis-layout` together.
+
+#define PI_TASK_IMPLEM_NB_DATA 8
+
+typedef struct pi_cl_dma_cmd_s
+{
+    int id;
+    struct pi_cl_dma_cmd_s *next;
+} pi_cl_dma_cmd_t;
+
+struct pi_device
+{
+    struct pi_device_api *api;
+    void *config;
+    void *data;
+};
+
+typedef struct pi_task
+{
+    struct pi_task *next;
+    uintptr_t arg[4];
+    int8_t done;
+    int id;
+    uint32_t data[PI_TASK_IMPLEM_NB_DATA];
+    // PI_TASK_IMPLEM
+    unsigned int time;
+} pi_task_t;
+
+typedef struct pi_cl_ram_req_s
+{
+    struct pi_device *device;
+    void *addr;
+    uint32_t pi_ram_addr;
+    uint32_t size;
+    int32_t stride;
+    uint32_t length;
+    pi_task_t event;
+    struct pi_cl_ram_req_s *next;
+    uint8_t done;
+    char cid;
+    char ext2loc;
+    char is_2d;
+} pi_cl_ram_req_t;
+
+struct pi_cluster_conf
+{
+    pi_device_e device_type;
+    int id;
+    void *heap_start;
+    uint32_t heap_size;
+    struct pmsis_event_kernel_wrap *event_kernel;
+    pi_cluster_flags_e flags;
+};
+
+struct pi_cluster_task
+{
+    void (*entry)(void*);
+    void *arg;
+    void *stacks;
+    uint32_t stack_size;
+    uint32_t slave_stack_size;
+    int nb_cores;
+    pi_task_t *completion_callback;
+    int stack_allocated;
+    struct pi_cluster_task *next;
+    int core_mask;
+};
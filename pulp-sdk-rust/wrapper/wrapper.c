@@ -2,6 +2,8 @@
 #include "stdint.h"
 #include "pmsis.h"
 #include <bsp/bsp.h>
+// Layout checks generated by build.rs
+#include "layout.h"
 
 void pi_cl_team_fork_wrap(int nb_cores, void (*entry)(void *), void *arg)
 {
@@ -13,10 +15,42 @@ void pi_cl_team_barrier_wrap()
     pi_cl_team_barrier();
 }
 
+void pi_cl_team_critical_enter_wrap()
+{
+    pi_cl_team_critical_enter();
+}
+
+void pi_cl_team_critical_exit_wrap()
+{
+    pi_cl_team_critical_exit();
+}
+
 void pi_cl_dma_cmd_wrap(uint32_t ext, uint32_t loc, uint32_t size, pi_cl_dma_dir_e dir, pi_cl_dma_cmd_t *cmd) {
     pi_cl_dma_cmd(ext, loc,size,dir, cmd);
 }
 
+uint32_t pi_cluster_id_wrap() {
+  return pi_cluster_id();
+}
+
+int pi_is_fc_wrap() {
+  return pi_is_fc();
+}
+
+int pi_cl_team_nb_cores_wrap() {
+  return pi_cl_team_nb_cores();
+}
+
+uint32_t pi_cl_l1_size_wrap() {
+  return ARCHI_L1_SIZE;
+}
+
+uint32_t pi_cl_l1_free_size_wrap() {
+  int size = 0;
+  pos_alloc_info(&pos_alloc_l1[0], &size, NULL, NULL);
+  return size;
+}
+
 void abort_all(){
   exit(1);
 }
@@ -54,4 +88,46 @@ void pi_cl_ram_write_wrap( 	struct pi_device *  	device,
 
 struct pi_cluster_task *pi_cluster_task_wrap(struct pi_cluster_task *task, void (*entry)(void*), void *arg) {
   return pi_cluster_task(task, entry, arg);
-}
\ No newline at end of file
+}
+
+pi_task_t *pi_task_block_wrap(pi_task_t *task) {
+  return pi_task_block(task);
+}
+
+void pi_task_wait_on_wrap(pi_task_t *task) {
+  pi_task_wait_on(task);
+}
+
+void eu_evt_mask_set_wrap(uint32_t mask) {
+  eu_evt_maskSet(mask);
+}
+
+void eu_evt_mask_clr_wrap(uint32_t mask) {
+  eu_evt_maskClr(mask);
+}
+
+uint32_t eu_evt_mask_wait_and_clr_wrap(uint32_t mask) {
+  return eu_evt_maskWaitAndClr(mask);
+}
+
+void eu_evt_clr_wrap(uint32_t mask) {
+  eu_evt_clr(mask);
+}
+
+void eu_evt_trig_sw_wrap(uint32_t event, uint32_t core_mask) {
+  eu_evt_trig(eu_evt_trig_addr(event), core_mask);
+}
+
+void pi_perf_cl_start_wrap() {
+  pi_perf_conf((1 << PI_PERF_CYCLES) | (1 << PI_PERF_INSTR) | (1 << PI_PERF_TCDM_CONT) | (1 << PI_PERF_LD_STALL));
+  pi_perf_reset();
+  pi_perf_start();
+}
+
+void pi_perf_cl_stop_wrap(uint32_t *counters) {
+  pi_perf_stop();
+  counters[0] = pi_perf_read(PI_PERF_CYCLES);
+  counters[1] = pi_perf_read(PI_PERF_INSTR);
+  counters[2] = pi_perf_read(PI_PERF_TCDM_CONT);
+  counters[3] = pi_perf_read(PI_PERF_LD_STALL);
+}
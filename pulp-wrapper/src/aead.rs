@@ -0,0 +1,287 @@
+use crate::uhash::{Field, GHash, HashCtx, Poly1305, BLOCK_LEN};
+use crate::*;
+use cipher::consts::{U12, U16, U32, U64};
+use cipher::{BlockCipher, BlockEncrypt, BlockSizeUser, IvSizeUser, KeyInit, KeySizeUser};
+
+/// Size of a ChaCha20 keystream block: the first one is used to derive the Poly1305 key
+const CHACHA_BLOCK: u64 = 64;
+pub const TAG_LEN: usize = 16;
+pub const GCM_IV_LEN: usize = 12;
+/// Longest GCM plaintext: the 32 bit counter starts at 2 and must not wrap around to J0
+const GCM_MAX_LEN: u64 = ((1 << 32) - 2) * BLOCK_LEN as u64;
+
+/// ChaCha20 with a 256 bit key, a 96 bit nonce and 64 byte keystream blocks, as used by
+/// ChaCha20-Poly1305 (RFC 8439).
+///
+/// Sealed and only implemented for `chacha20::ChaCha20` and the ChaCha20 of the Xpulp fork,
+/// so that [PulpWrapper::seal] and [PulpWrapper::open] can't be used with e.g. ChaCha12 or Salsa20,
+/// which have the same sizes.
+pub trait ChaCha20Ietf:
+    sealed::Sealed
+    + StreamCipher
+    + StreamCipherSeek
+    + KeyIvInit
+    + KeySizeUser<KeySize = U32>
+    + IvSizeUser<IvSize = U12>
+    + KeystreamBlock<BlockSize = U64>
+{
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl sealed::Sealed for chacha20::ChaCha20 {}
+impl ChaCha20Ietf for chacha20::ChaCha20 {}
+
+impl sealed::Sealed for chacha20_pulp::ChaCha20 {}
+impl ChaCha20Ietf for chacha20_pulp::ChaCha20 {}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Encrypt data in [source] with ChaCha20-Poly1305 (RFC 8439) and return the
+    /// authentication tag. Each core authenticates its part of the ciphertext while it's in L1.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn seal<C: ChaCha20Ietf>(
+        &mut self,
+        source: &mut [u8],
+        aad: &[u8],
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        loc: SourceLocation,
+    ) -> Result<[u8; TAG_LEN], WrapperError> {
+        let (r, s) = poly1305_key::<C>(key, iv);
+        let lengths = lengths_le(aad.len(), source.len());
+        let hash =
+            self.hash_stream::<C, Poly1305>(source, key, iv, CHACHA_BLOCK, loc, r, aad, &lengths)?;
+        Ok(hash.finalize(&s))
+    }
+
+    /// Decrypt data in [source] with ChaCha20-Poly1305 (RFC 8439) if it matches [tag].
+    ///
+    /// The ciphertext is authenticated first, only reading it: if the tag does not match,
+    /// [source] is left untouched and [WrapperError::TagMismatch] is returned.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn open<C: ChaCha20Ietf>(
+        &mut self,
+        source: &mut [u8],
+        aad: &[u8],
+        tag: &[u8; TAG_LEN],
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        loc: SourceLocation,
+    ) -> Result<(), WrapperError> {
+        Self::check::<C>(key, iv, source.len(), loc, loc, CHACHA_BLOCK)?;
+        let (r, s) = poly1305_key::<C>(key, iv);
+        let lengths = lengths_le(aad.len(), source.len());
+        let expected = self.hash_ciphertext(source, loc, r, aad, &lengths)?.finalize(&s);
+        if !tags_match(&expected, tag) {
+            return Err(WrapperError::TagMismatch);
+        }
+        self.run_at::<C>(source, key, iv, loc, CHACHA_BLOCK).map(|_| ())
+    }
+
+    /// Encrypt data in [source] with AES-GCM and return the authentication tag.
+    /// Each core computes GHASH over its part of the ciphertext while it's in L1.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn seal_gcm<A>(
+        &mut self,
+        source: &mut [u8],
+        aad: &[u8],
+        key: &GenericArray<u8, A::KeySize>,
+        iv: &[u8; GCM_IV_LEN],
+        loc: SourceLocation,
+    ) -> Result<[u8; TAG_LEN], WrapperError>
+    where
+        A: BlockEncrypt + BlockCipher + BlockSizeUser<BlockSize = U16> + KeyInit,
+    {
+        if source.len() as u64 > GCM_MAX_LEN {
+            return Err(WrapperError::KeystreamOverflow);
+        }
+        let (h, ctr_iv, mask) = gcm_init::<A>(key, iv);
+        let lengths = lengths_be(aad.len(), source.len());
+        let hash = self.hash_stream::<ctr::Ctr32BE<A>, GHash>(
+            source, key, &ctr_iv, 0, loc, h, aad, &lengths,
+        )?;
+        Ok(xor_tag(hash.to_bytes(), &mask))
+    }
+
+    /// Decrypt data in [source] with AES-GCM if it matches [tag].
+    ///
+    /// The ciphertext is authenticated first, only reading it: if the tag does not match,
+    /// [source] is left untouched and [WrapperError::TagMismatch] is returned, so that
+    /// no unauthenticated plaintext is released.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn open_gcm<A>(
+        &mut self,
+        source: &mut [u8],
+        aad: &[u8],
+        tag: &[u8; TAG_LEN],
+        key: &GenericArray<u8, A::KeySize>,
+        iv: &[u8; GCM_IV_LEN],
+        loc: SourceLocation,
+    ) -> Result<(), WrapperError>
+    where
+        A: BlockEncrypt + BlockCipher + BlockSizeUser<BlockSize = U16> + KeyInit,
+    {
+        if source.len() as u64 > GCM_MAX_LEN {
+            return Err(WrapperError::KeystreamOverflow);
+        }
+        let (h, ctr_iv, mask) = gcm_init::<A>(key, iv);
+        Self::check::<ctr::Ctr32BE<A>>(key, &ctr_iv, source.len(), loc, loc, 0)?;
+        let lengths = lengths_be(aad.len(), source.len());
+        let hash = self.hash_ciphertext(source, loc, h, aad, &lengths)?;
+        if !tags_match(&xor_tag(hash.to_bytes(), &mask), tag) {
+            return Err(WrapperError::TagMismatch);
+        }
+        self.run_at::<ctr::Ctr32BE<A>>(source, key, &ctr_iv, loc, 0).map(|_| ())
+    }
+
+    /// Hash the ciphertext in [source] as `aad || ciphertext || lengths`, only reading it
+    unsafe fn hash_ciphertext<F: Field>(
+        &mut self,
+        source: &[u8],
+        loc: SourceLocation,
+        h: F,
+        aad: &[u8],
+        lengths: &[u8; BLOCK_LEN],
+    ) -> Result<F, WrapperError> {
+        let mut partials = [F::ZERO; CORES];
+        let ctx = HashCtx::new(h, source.len(), partials.as_mut_ptr());
+        let data = self.read_only_data(source, loc, &ctx as *const HashCtx<F> as *const cty::c_void)?;
+        self.execute(Self::hash_entry_point::<F>, data)?;
+        Ok(ctx.finish(&partials, aad, lengths))
+    }
+
+    extern "C" fn hash_entry_point<F: Field>(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let hash = &*(data.ctx as *const HashCtx<F>);
+            let mut partial = F::ZERO;
+            Self::for_each_chunk_read_only(data, Partition::new(Self::MAC_ALIGN, 0), |chunk, pos| {
+                partial = partial.add(&hash.partial(chunk, pos));
+            });
+            hash.set_partial(pi_core_id(), partial);
+        }
+    }
+
+    /// Encrypt [source] and hash the ciphertext as `aad || ciphertext || lengths`
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn hash_stream<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock, F: Field>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        stream_offset: u64,
+        loc: SourceLocation,
+        h: F,
+        aad: &[u8],
+        lengths: &[u8; BLOCK_LEN],
+    ) -> Result<F, WrapperError> {
+        Self::check::<C>(key, iv, source.len(), loc, loc, stream_offset)?;
+        let mut partials = [F::ZERO; CORES];
+        let ctx = HashCtx::new(h, source.len(), partials.as_mut_ptr());
+        let mut data = CoreData::new(
+            source.as_mut_ptr(),
+            source.len(),
+            &self.cluster_buffer,
+            key.as_ptr(),
+            iv.as_ptr(),
+            loc,
+        );
+        data.stream_offset = stream_offset;
+        data.ctx = &ctx as *const HashCtx<F> as *const cty::c_void;
+        self.execute(Self::aead_entry_point::<C, F>, data)?;
+
+        Ok(ctx.finish(&partials, aad, lengths))
+    }
+
+    extern "C" fn aead_entry_point<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock, F: Field>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+    ) {
+        unsafe {
+            let mut cipher = data.cipher::<C>();
+            let stream_offset = data.stream_offset;
+            let hash = &*(data.ctx as *const HashCtx<F>);
+            let mut partial = F::ZERO;
+            // every core must also start on a block of the hash
+            let align = CoreAlign::<C, CORES, BUF_LEN, BLOCK_LEN>::VALUE;
+            debug_assert_eq!(stream_offset % align as u64, 0);
+            let partition = Partition::new(align, stream_offset);
+            Self::for_each_chunk(data, partition, |mut chunk, pos| {
+                if cipher.try_seek(stream_offset + pos as u64).is_err() {
+                    data.raise(WrapperError::KeystreamOverflow);
+                    return;
+                }
+                cipher.apply_keystream_inout(chunk.reborrow());
+                // authenticate the ciphertext
+                partial = partial.add(&hash.partial(chunk.get_in(), pos));
+            });
+            hash.set_partial(pi_core_id(), partial);
+        }
+    }
+}
+
+/// One-time Poly1305 key from the first keystream block
+fn poly1305_key<C: StreamCipher + KeyIvInit>(
+    key: &GenericArray<u8, C::KeySize>,
+    iv: &GenericArray<u8, C::IvSize>,
+) -> (Poly1305, [u8; 16]) {
+    let mut poly_key = [0u8; 32];
+    C::new(key, iv).apply_keystream(&mut poly_key);
+    let (r, s) = poly_key.split_at(16);
+    (Poly1305::from_key(r.try_into().unwrap()), s.try_into().unwrap())
+}
+
+/// Hash key, initial counter block for encryption and tag mask
+fn gcm_init<A: BlockEncrypt + BlockSizeUser<BlockSize = U16> + KeyInit>(
+    key: &GenericArray<u8, A::KeySize>,
+    iv: &[u8; GCM_IV_LEN],
+) -> (GHash, GenericArray<u8, U16>, [u8; TAG_LEN]) {
+    let cipher = A::new(key);
+    let mut h = GenericArray::default();
+    cipher.encrypt_block(&mut h);
+
+    let mut j0 = GenericArray::<u8, U16>::default();
+    j0[..GCM_IV_LEN].copy_from_slice(iv);
+    j0[15] = 1;
+    let mut ctr_iv = j0;
+    ctr_iv[15] = 2;
+    cipher.encrypt_block(&mut j0);
+
+    (GHash::from_block(&h.into()), ctr_iv, j0.into())
+}
+
+fn lengths_le(aad_len: usize, len: usize) -> [u8; BLOCK_LEN] {
+    let mut lengths = [0; BLOCK_LEN];
+    lengths[..8].copy_from_slice(&(aad_len as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(len as u64).to_le_bytes());
+    lengths
+}
+
+fn lengths_be(aad_len: usize, len: usize) -> [u8; BLOCK_LEN] {
+    let mut lengths = [0; BLOCK_LEN];
+    lengths[..8].copy_from_slice(&(aad_len as u64 * 8).to_be_bytes());
+    lengths[8..].copy_from_slice(&(len as u64 * 8).to_be_bytes());
+    lengths
+}
+
+/// Compare tags in constant time
+fn tags_match(expected: &[u8; TAG_LEN], tag: &[u8; TAG_LEN]) -> bool {
+    expected.iter().zip(tag).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn xor_tag(mut tag: [u8; TAG_LEN], mask: &[u8; TAG_LEN]) -> [u8; TAG_LEN] {
+    tag.iter_mut().zip(mask).for_each(|(t, m)| *t ^= m);
+    tag
+}
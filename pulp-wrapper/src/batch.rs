@@ -0,0 +1,235 @@
+use crate::buf::DmaQueue;
+use crate::*;
+use core::pin::{pin, Pin};
+
+/// An independent message of a batch, with its own key and iv
+pub struct Job<'a, C: KeyIvInit> {
+    pub data: &'a mut [u8],
+    pub key: &'a GenericArray<u8, C::KeySize>,
+    pub iv: &'a GenericArray<u8, C::IvSize>,
+    pub loc: SourceLocation,
+}
+
+// Start of each job in L1 is aligned for full DMA bandwidth
+const JOB_ALIGN: usize = <Target as Platform>::DMA_ALIGN;
+// Transfers core 0 keeps in flight, more only wait for the oldest one
+const DMA_SLOTS: usize = 4;
+
+impl<'a, C: KeyIvInit> Job<'a, C> {
+    /// Space taken in the L1 buffer of a round
+    fn l1_len(&self) -> usize {
+        match self.loc {
+            SourceLocation::L1 => 0,
+            _ => (self.data.len() + JOB_ALIGN - 1) / JOB_ALIGN * JOB_ALIGN,
+        }
+    }
+}
+
+/// Jobs of a batch shared with the cores, see [PulpWrapper::run_batch]
+struct BatchCtx<'a, C: KeyIvInit> {
+    jobs: *mut Job<'a, C>,
+    // jobs that failed the checks are skipped
+    results: *const Result<(), WrapperError>,
+}
+
+/// Jobs packed in a single round, in order, as index in the batch with their offset in L1
+/// and the core processing them.
+///
+/// Every core walks the same rounds, so that the plan doesn't need to be shared.
+struct Round<'j, 'a, C: KeyIvInit, const CORES: usize> {
+    jobs: &'j [Job<'a, C>],
+    results: &'j [Result<(), WrapperError>],
+    next: usize,
+    capacity: usize,
+    used: usize,
+    // bytes assigned to each core in this round
+    load: [usize; CORES],
+}
+
+impl<'j, 'a, C: KeyIvInit, const CORES: usize> Round<'j, 'a, C, CORES> {
+    fn new(
+        jobs: &'j [Job<'a, C>],
+        results: &'j [Result<(), WrapperError>],
+        start: usize,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            jobs,
+            results,
+            next: start,
+            capacity,
+            used: 0,
+            load: [0; CORES],
+        }
+    }
+
+    /// First job of the next round
+    fn end(mut self) -> usize {
+        for _ in &mut self {}
+        self.next
+    }
+}
+
+impl<'j, 'a, C: KeyIvInit, const CORES: usize> Iterator for Round<'j, 'a, C, CORES> {
+    type Item = (usize, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let job = self.jobs.get(self.next)?;
+            let len = job.data.len();
+            let l1_len = job.l1_len();
+            // invalid, empty or too big for a round, the latter are streamed separately
+            if self.results[self.next].is_err() || len == 0 || l1_len > self.capacity {
+                self.next += 1;
+                continue;
+            }
+            if self.used + l1_len > self.capacity {
+                return None;
+            }
+            let index = self.next;
+            self.next += 1;
+            // least loaded core
+            let core = (0..CORES).min_by_key(|&c| self.load[c]).unwrap();
+            self.load[core] += len;
+            let offset = self.used;
+            self.used += l1_len;
+            return Some((index, offset, core));
+        }
+    }
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Encrypt / decrypt many independent messages with a single cluster task,
+    /// writing the outcome of each job to the same entry of [results].
+    ///
+    /// Messages are packed in rounds of the L1 buffers and spread across cores by size,
+    /// the next round is fetched while the current one is processed; those not fitting
+    /// in a buffer are streamed one at a time as with [run].
+    ///
+    /// Each job is checked on its own before starting: invalid ones are skipped and do not
+    /// prevent the others from being processed. Fails with [WrapperError::InvalidLength],
+    /// without processing any job, if [results] and [jobs] differ in length.
+    ///
+    /// # Safety:
+    /// * location of every job must be correctly specified
+    /// * if present, ram device pointers must be valid to read for the whole duration
+    pub unsafe fn run_batch<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        &mut self,
+        jobs: &mut [Job<'_, C>],
+        results: &mut [Result<(), WrapperError>],
+    ) -> Result<(), WrapperError> {
+        if results.len() != jobs.len() {
+            return Err(WrapperError::InvalidLength);
+        }
+        for (job, result) in jobs.iter().zip(results.iter_mut()) {
+            *result = Self::check::<C>(job.key, job.iv, job.data.len(), job.loc, job.loc, 0).map(|_| ());
+        }
+        let ctx = BatchCtx {
+            jobs: jobs.as_mut_ptr(),
+            results: results.as_ptr(),
+        };
+        let mut data = CoreData::new(
+            core::ptr::null_mut(),
+            jobs.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            SourceLocation::L2,
+        );
+        data.ctx = &ctx as *const BatchCtx<C> as *const cty::c_void;
+        let batch = self.execute(Self::batch_entry_point::<C>, data);
+
+        for (job, result) in jobs.iter_mut().zip(results.iter_mut()) {
+            if result.is_err() || job.data.is_empty() {
+                continue;
+            }
+            *result = if job.l1_len() > BUF_LEN {
+                self.run::<C>(job.data, job.key, job.iv, job.loc).map(|_| ())
+            } else {
+                batch
+            };
+        }
+        Ok(())
+    }
+
+    /// Rounds go through the [DEPTH] L1 buffers in turn: while the cores work on a round,
+    /// core 0 fetches the next one and the previous one is still being committed.
+    extern "C" fn batch_entry_point<C: StreamCipher + KeyIvInit>(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let ctx = &*(data.ctx as *const BatchCtx<C>);
+            // the cores write to the data of the jobs, which are only read through this slice
+            let jobs = core::slice::from_raw_parts(ctx.jobs, data.len);
+            let data_ptr = |index: usize| (*ctx.jobs.add(index)).data.as_mut_ptr();
+            let results = core::slice::from_raw_parts(ctx.results, data.len);
+            let round = |start: usize| <Round<C, CORES>>::new(jobs, results, start, BUF_LEN);
+            let l1_alloc = &*data.l1_alloc;
+            let core_id = pi_core_id();
+            // Only core 0 interacts with the dma
+            let mut dma = pin!(DmaQueue::<DMA_SLOTS>::new());
+            // transfers to wait for before reusing each buffer, i.e. the commits of its last round
+            let mut buffer_free = [0; DEPTH];
+            let fetch = |mut dma: Pin<&mut DmaQueue<DMA_SLOTS>>, start: usize, l1: *mut u8| {
+                for (index, offset, _) in round(start) {
+                    let job = &jobs[index];
+                    if !matches!(job.loc, SourceLocation::L1) {
+                        dma.as_mut().copy_in(job.loc, data_ptr(index), l1.add(offset), job.data.len());
+                    }
+                }
+            };
+
+            let mut start = 0;
+            let mut next = round(start).end();
+            if core_id == 0 {
+                fetch(dma.as_mut(), start, l1_alloc.buffer(0));
+                dma.as_mut().wait_all();
+            }
+            pi_cl_team_barrier();
+
+            let mut rounds = 0;
+            while start < jobs.len() {
+                let l1 = l1_alloc.buffer(rounds);
+                let mut fetched = 0;
+                if core_id == 0 && next < jobs.len() {
+                    dma.as_mut().wait_until(buffer_free[(rounds + 1) % DEPTH]);
+                    fetch(dma.as_mut(), next, l1_alloc.buffer(rounds + 1));
+                    fetched = dma.started();
+                }
+
+                for (index, offset, core) in round(start) {
+                    if core != core_id {
+                        continue;
+                    }
+                    let job = &jobs[index];
+                    let buf = match job.loc {
+                        SourceLocation::L1 => data_ptr(index),
+                        _ => l1.add(offset),
+                    };
+                    let res = C::new(job.key, job.iv)
+                        .try_apply_keystream(core::slice::from_raw_parts_mut(buf, job.data.len()));
+                    if res.is_err() {
+                        data.raise(WrapperError::KeystreamOverflow);
+                    }
+                }
+                pi_cl_team_barrier();
+
+                if core_id == 0 {
+                    for (index, offset, _) in round(start) {
+                        let job = &jobs[index];
+                        if !matches!(job.loc, SourceLocation::L1) {
+                            dma.as_mut().copy_out(job.loc, data_ptr(index), l1.add(offset), job.data.len());
+                        }
+                    }
+                    buffer_free[rounds % DEPTH] = dma.started();
+                    // commits go on during the next round
+                    dma.as_mut().wait_until(fetched);
+                }
+                // the next round is in L1
+                pi_cl_team_barrier();
+                start = next;
+                next = round(start).end();
+                rounds += 1;
+            }
+            dma.as_mut().wait_all();
+        }
+    }
+}
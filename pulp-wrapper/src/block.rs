@@ -0,0 +1,163 @@
+use crate::partition::BlockAlign;
+use crate::*;
+use cipher::{Block, BlockCipher, BlockDecrypt, BlockDecryptMut, BlockEncrypt, BlockSizeUser, InnerIvInit, KeyInit};
+
+/// Last ciphertext block of the slice of each core, which the next slice needs for CBC decryption
+struct CbcCtx<B: BlockSizeUser, const CORES: usize> {
+    iv: Block<B>,
+    // one slot per core for even and odd rounds, so that a round can read the tails of the
+    // previous one while they are being overwritten
+    tails: *mut [Block<B>; CORES],
+}
+
+impl<B: BlockSizeUser, const CORES: usize> CbcCtx<B, CORES> {
+    /// Slots of the cores in [round]
+    fn tails(&self, round: usize) -> *mut Block<B> {
+        unsafe { self.tails.add(round % 2).cast() }
+    }
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Encrypt data in [source] with the block cipher `B` in ECB mode.
+    /// The length must be a multiple of the block size.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn ecb_encrypt<B: BlockEncrypt + KeyInit + Clone>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, B::KeySize>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        self.run_blocks(source, &B::new(key), core::ptr::null(), loc, Self::ecb_encrypt_entry_point::<B>)
+    }
+
+    /// Decrypt data in [source] with the block cipher `B` in ECB mode.
+    /// The length must be a multiple of the block size.
+    ///
+    /// # Safety:
+    /// * same as [ecb_encrypt](Self::ecb_encrypt)
+    pub unsafe fn ecb_decrypt<B: BlockDecrypt + KeyInit + Clone>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, B::KeySize>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        self.run_blocks(source, &B::new(key), core::ptr::null(), loc, Self::ecb_decrypt_entry_point::<B>)
+    }
+
+    /// Decrypt data in [source] with the block cipher `B` in CBC mode.
+    /// The length must be a multiple of the block size.
+    ///
+    /// Every block only depends on the previous ciphertext block, so each core decrypts its slice
+    /// after the cores have shared the last ciphertext block of their slices.
+    ///
+    /// # Safety:
+    /// * same as [ecb_encrypt](Self::ecb_encrypt)
+    pub unsafe fn cbc_decrypt<B: BlockDecrypt + BlockCipher + KeyInit + Clone>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, B::KeySize>,
+        iv: &Block<B>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        let mut tails: [[Block<B>; CORES]; 2] =
+            core::array::from_fn(|_| core::array::from_fn(|_| Block::<B>::default()));
+        let ctx = CbcCtx::<B, CORES> {
+            iv: iv.clone(),
+            tails: tails.as_mut_ptr(),
+        };
+        self.run_blocks(
+            source,
+            &B::new(key),
+            &ctx as *const CbcCtx<B, CORES> as *const cty::c_void,
+            loc,
+            Self::cbc_decrypt_entry_point::<B>,
+        )
+    }
+
+    /// Run [f] on every core with [cipher] shared by the FC and the mode-specific [ctx]
+    ///
+    /// # Safety:
+    /// * same as [ecb_encrypt](Self::ecb_encrypt)
+    unsafe fn run_blocks<B: BlockSizeUser + Clone>(
+        &mut self,
+        source: &mut [u8],
+        cipher: &B,
+        ctx: *const cty::c_void,
+        loc: SourceLocation,
+        f: extern "C" fn(&CoreData<BUF_LEN, DEPTH>),
+    ) -> Result<RunReport, WrapperError> {
+        if source.len() % B::BlockSize::USIZE != 0 {
+            return Err(WrapperError::InvalidLength);
+        }
+        let report = Self::check_keystream(None, source.len(), loc, loc, 0)?;
+        let mut data = CoreData::new(
+            source.as_mut_ptr(),
+            source.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            loc,
+        );
+        data.ctx = ctx;
+        self.execute_shared(f, data, cipher)?;
+        Ok(report)
+    }
+
+    extern "C" fn ecb_encrypt_entry_point<B: BlockEncrypt + Clone>(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let cipher = data.shared_cipher::<B>();
+            let partition = Partition::new(BlockAlign::<B, CORES, BUF_LEN>::VALUE, 0);
+            Self::for_each_chunk(data, partition, |chunk, _| {
+                cipher.encrypt_blocks_inout(chunk.into_chunks().0);
+            });
+        }
+    }
+
+    extern "C" fn ecb_decrypt_entry_point<B: BlockDecrypt + Clone>(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let cipher = data.shared_cipher::<B>();
+            let partition = Partition::new(BlockAlign::<B, CORES, BUF_LEN>::VALUE, 0);
+            Self::for_each_chunk(data, partition, |chunk, _| {
+                cipher.decrypt_blocks_inout(chunk.into_chunks().0);
+            });
+        }
+    }
+
+    extern "C" fn cbc_decrypt_entry_point<B: BlockDecrypt + BlockCipher + Clone>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+    ) {
+        unsafe {
+            let cipher = data.shared_cipher::<B>();
+            let ctx = &*(data.ctx as *const CbcCtx<B, CORES>);
+            let core_id = pi_core_id();
+            // data in L1 is a single round covering the whole source
+            let in_l1 = matches!(data.loc, SourceLocation::L1);
+            let partition = Partition::new(BlockAlign::<B, CORES, BUF_LEN>::VALUE, 0);
+            Self::for_each_chunk(data, partition, |chunk, pos| {
+                let (round, offset) = if in_l1 { (0, pos) } else { (pos / BUF_LEN, pos % BUF_LEN) };
+                let (blocks, _) = chunk.into_chunks::<B::BlockSize>();
+                let tails = ctx.tails(round);
+                // publish the last ciphertext block before anyone decrypts in place
+                if let Some(last) = blocks.get_in().last() {
+                    *tails.add(core_id) = last.clone();
+                }
+                pi_cl_team_barrier();
+                if blocks.is_empty() {
+                    return;
+                }
+                // slices of a round are contiguous, and all the rounds before the last one are full
+                let prev = if pos == 0 {
+                    &ctx.iv
+                } else if offset == 0 {
+                    &*ctx.tails(round - 1).add(CORES - 1)
+                } else {
+                    &*tails.add(core_id - 1)
+                };
+                cbc::Decryptor::<B>::inner_iv_init(cipher.clone(), prev).decrypt_blocks_inout_mut(blocks);
+            });
+        }
+    }
+}
@@ -1,44 +1,124 @@
-use crate::Cluster;
+use crate::partition::Partition;
+use crate::{Cluster, SourceLocation, WrapperError};
 use ::pulp_sdk_rust::*;
 use alloc::boxed::Box;
 use cipher::inout::InOutBuf;
+use core::alloc::AllocError;
 use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
 use core::ptr::NonNull;
 
+
+// L1 banks are interleaved on 32 bit words
+const WORD: usize = 4;
+
+/// How the buffers of a [BufAlloc] are placed in the word-interleaved L1 banks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BufLayout {
+    /// Each buffer is contiguous and a whole round is moved by core 0 with a single transfer.
+    ///
+    /// Core slices are a multiple of the cipher block, so with 64 byte blocks all cores
+    /// start on the same bank and contend for it while working in lockstep.
+    #[default]
+    Contiguous,
+    /// The slice of each core is shifted by a few words, so that cores working in lockstep
+    /// access different banks. Each core moves its own slice, which also spreads
+    /// the DMA traffic of a round over the banks instead of a single sweep.
+    /// Ram requests are queued to the FC by one core at a time, see [DmaIn::start].
+    Interleaved,
+}
+
+/// L1 allocation backing a [DmaBuf], holding [DEPTH] buffers of [BUF_LEN] bytes
+/// (plus padding with [BufLayout::Interleaved]).
+///
+/// Allocated once from the FC and shared by all cores.
 // newtype around owned naked pointer to guarantee proper allocation and handling
-pub(crate) struct BufAlloc<'a, const BUF_LEN: usize> {
+pub struct BufAlloc<'a, const BUF_LEN: usize, const DEPTH: usize> {
     buf: *mut u8,
     allocator: ClusterAllocator<'a>,
+    layout: BufLayout,
 }
 
+/// Data in external memory (L2 or ram) streamed through a [DmaBuf].
+///
+/// Conceptually a mutable slice but with aliasing in different cores
 // Newtype around naked pointer to guarantee proper handling
-//
-// Conceptually a mutable slice but with aliasing in different cores
-pub(crate) struct SourcePtr<'a> {
+pub struct SourcePtr<'a> {
     ptr: *mut u8,
     len: usize,
     _lifetime: PhantomData<&'a u8>,
 }
 
-impl<'alloc, const BUF_LEN: usize> BufAlloc<'alloc, BUF_LEN> {
-    pub fn new<const CORES: usize>(cluster: &'alloc Cluster<CORES>) -> Self {
+impl<'alloc, const BUF_LEN: usize, const DEPTH: usize> BufAlloc<'alloc, BUF_LEN, DEPTH> {
+    /// Padding after each buffer with [BufLayout::Interleaved], enough to shift
+    /// the slices of up to [L1_BANKS](Platform::L1_BANKS) cores by a word each
+    pub const PAD: usize = WORD * <Target as Platform>::L1_BANKS;
+
+    /// Size of the L1 allocation with [layout], in bytes
+    pub const fn size(layout: BufLayout) -> usize {
+        assert!(DEPTH >= 2, "the DMA pipeline needs at least 2 buffers");
+        match layout {
+            BufLayout::Contiguous => BUF_LEN * DEPTH,
+            BufLayout::Interleaved => (BUF_LEN + Self::PAD) * DEPTH,
+        }
+    }
+
+    /// Allocate contiguous buffers in the L1 memory of [cluster]
+    pub fn new<const CORES: usize>(cluster: &'alloc Cluster<CORES>) -> Result<Self, AllocError> {
+        Self::new_with_layout(cluster, BufLayout::Contiguous)
+    }
+
+    /// Allocate the buffers in the L1 memory of [cluster] with the given layout
+    pub fn new_with_layout<const CORES: usize>(
+        cluster: &'alloc Cluster<CORES>,
+        layout: BufLayout,
+    ) -> Result<Self, AllocError> {
         let allocator = cluster.l1_allocator();
         // SAFETY: u8 are always valid, and this will be overwritten before actual use by DMA
-        let buf =
-            unsafe { Box::leak(Box::new_uninit_slice_in(BUF_LEN * 3, allocator).assume_init()) };
+        let buf = unsafe {
+            Box::leak(Box::try_new_uninit_slice_in(Self::size(layout), allocator)?.assume_init())
+        };
 
-        Self {
+        Ok(Self {
             buf: buf.as_mut_ptr(),
             allocator,
+            layout,
+        })
+    }
+
+    /// Start of the L1 allocation, valid for [len](Self::len) bytes
+    pub fn ptr(&self) -> *mut u8 {
+        self.buf
+    }
+
+    /// Size of the L1 allocation, in bytes
+    pub fn len(&self) -> usize {
+        Self::size(self.layout)
+    }
+
+    pub fn layout(&self) -> BufLayout {
+        self.layout
+    }
+
+    /// Distance between the start of two consecutive buffers
+    fn stride(&self) -> usize {
+        match self.layout {
+            BufLayout::Contiguous => BUF_LEN,
+            BufLayout::Interleaved => BUF_LEN + Self::PAD,
         }
     }
+
+    /// Start of buffer [index] (modulo [DEPTH]), valid for [BUF_LEN] bytes
+    pub(crate) fn buffer(&self, index: usize) -> *mut u8 {
+        unsafe { self.buf.add(index % DEPTH * self.stride()) }
+    }
 }
 
-impl<'alloc, const BUF_LEN: usize> Drop for BufAlloc<'alloc, BUF_LEN> {
+impl<'alloc, const BUF_LEN: usize, const DEPTH: usize> Drop for BufAlloc<'alloc, BUF_LEN, DEPTH> {
     fn drop(&mut self) {
         let _ = unsafe {
             Box::from_raw_in(
-                core::slice::from_raw_parts_mut(self.buf, BUF_LEN),
+                core::slice::from_raw_parts_mut(self.buf, self.len()),
                 self.allocator,
             )
         };
@@ -50,7 +130,6 @@ impl<'a> SourcePtr<'a> {
     /// The memory referenced by the slice must not be accessed through any
     /// other pointer (including the original slice) for the duration of
     /// lifetime 'a. Both read and write accesses are forbidden.
-    #[allow(unused)]
     pub unsafe fn from_mut_slice(slice: &'a mut [u8]) -> Self {
         SourcePtr {
             ptr: slice.as_mut_ptr(),
@@ -77,9 +156,14 @@ impl<'a> SourcePtr<'a> {
 }
 
 /// A managed buffer in L1 cache with automatic DMA transfers in and out based on
-/// rounds
+/// rounds, for kernels working in place on data in external memory.
+///
 /// Since we need to transfer back the modified data, we divide the L1 allocation
-/// in 3 buffers: work, pre-fetch and commit
+/// in [DEPTH] buffers used in turn: one for work, one for the commit of the previous round
+/// and the others to pre-fetch the next [DEPTH] - 2 rounds.
+/// With [DEPTH] = 2 the next round is only fetched once the previous one has been committed.
+///
+/// For example, with [DEPTH] = 3:
 ///
 ///  round: 0
 ///    work         dma in(pre-fetch)
@@ -95,21 +179,52 @@ impl<'a> SourcePtr<'a> {
 ///   dma in (pre-fetch) dma out (commit) work
 ///         |              |              |
 /// |--------------|--------------|---------------|
-pub(crate) struct DmaBuf<'alloc, 'buf, 'source, const CORES: usize, const BUF_LEN: usize> {
+///
+/// Every core of the team builds its own [DmaBuf] over the same data and calls the same
+/// methods in the same order, as they synchronize on barriers. With [BufLayout::Contiguous]
+/// only core 0 drives the DMA, with [BufLayout::Interleaved] each core moves its own slices.
+/// The DMA descriptors are part of the struct, so it must be pinned (e.g. with
+/// [core::pin::pin]) before starting any round. The simplest use is [process](Self::process),
+/// which runs all rounds and flushes:
+///
+/// ```ignore
+/// let buf = pin!(DmaBuf::<CORES, BUF_LEN, DEPTH>::new(source, loc, dest, loc, &alloc, 4)?);
+/// buf.process(|mut chunk, pos| kernel(chunk.get_out(), pos))?;
+/// ```
+///
+/// Rounds can also be driven manually with [get_work_buf](Self::get_work_buf),
+/// [advance](Self::advance) and [flush](Self::flush), which report calls out of order
+/// with [WrapperError::InvalidState].
+///
+/// The core and the team are only checked by [new](Self::new): the buffer holds raw pointers
+/// to the data, so it is not [Send] and can't leave the core that built it, and the team of
+/// a cluster task does not change while it runs.
+pub struct DmaBuf<
+    'alloc,
+    'buf,
+    'source,
+    const CORES: usize,
+    const BUF_LEN: usize,
+    const DEPTH: usize,
+> {
     // data in external memory
     source: SourcePtr<'source>,
-    // allocation in L1 cache
-    // Ideally the blocks are layed out in memory so that each core's block lays entirely on a different l1 bank so that we minimize contention
-    // on the same bank. Probably we could force this knowing the memory addresses layout
-    // DMA should also operate on separate banks
-    l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN>,
+    // where processed data is committed, might be the same as source
+    dest: SourcePtr<'source>,
+    // allocation in L1 cache, see [BufLayout] for how it's split among cores
+    l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN, DEPTH>,
     // how many rounds have been completed till now
     rounds: usize,
-    pre_fetch_dma: DmaTransfer,
-    commit_dma: DmaTransfer,
-    counters: [usize; 3],
-    last_transfer: usize,
-    work_buf_len: usize,
+    // one for each buffer, as more than one pre-fetch can be in flight
+    pre_fetch_dma: [DmaIn; DEPTH],
+    commit_dma: DmaOut,
+    // transfers started by this core and not yet waited for
+    pre_fetch_pending: [bool; DEPTH],
+    commit_pending: bool,
+    flushed: bool,
+    // work buffers are never committed, the source is only read
+    read_only: bool,
+    partition: Partition<CORES>,
 }
 
 enum DmaTransfer {
@@ -123,9 +238,14 @@ enum DmaTransfer {
     },
 }
 
-// TODO: ensure can't be unpinned
-// It's safe for now as it's a private struct and we know that we never move it,
-// but it will be necessary to make [DmaBuf] a public struct.
+/// Transfers from external memory to L1
+struct DmaIn(DmaTransfer);
+
+/// Transfers from L1 to external memory
+struct DmaOut(DmaTransfer);
+
+// Descriptors are used by the DMA while a transfer is in flight: [DmaBuf] only starts
+// transfers through a pinned reference, and waits for those still in flight when dropped.
 impl DmaTransfer {
     pub fn new_l2() -> Self {
         Self::L2 {
@@ -141,193 +261,486 @@ impl DmaTransfer {
         }
     }
 
-    unsafe fn transfer_in(&mut self, remote: *mut u8, l1: *mut u8, len: usize) {
-        match self {
-            Self::Ram { ref mut req, .. } => {
+    pub fn new(loc: SourceLocation) -> Self {
+        match loc {
+            SourceLocation::L2 => Self::new_l2(),
+            SourceLocation::Ram(device) => Self::new_ram(device),
+            // rejected by PulpWrapper before starting the cluster
+            SourceLocation::L1 => unreachable!("no DMA transfers from / to L1"),
+        }
+    }
+}
+
+impl DmaIn {
+    // Ram transfers are requests served by the FC, which the runtime does not expect from
+    // several cores at once: they are queued inside the team critical section, and then
+    // served one after the other anyway. Each core only waits for its own requests.
+    unsafe fn start(&mut self, remote: *mut u8, l1: *mut u8, len: usize) {
+        match &mut self.0 {
+            DmaTransfer::Ram { ref mut req, .. } => {
+                pi_cl_team_critical_enter();
                 pi_cl_ram_read(req.device(), remote, l1, len, req);
+                pi_cl_team_critical_exit();
             }
-            Self::L2 { ref mut cmd, .. } => {
+            DmaTransfer::L2 { ref mut cmd, .. } => {
                 pi_cl_dma_cmd(remote, l1, len, PiClDmaDirE::PI_CL_DMA_DIR_EXT2LOC, cmd);
             }
         }
     }
 
-    unsafe fn transfer_out(&mut self, remote: *mut u8, l1: *mut u8, len: usize) {
-        match self {
-            Self::Ram { ref mut req, .. } => {
+    // Safety: do not call on uninitialized requests
+    unsafe fn wait(&mut self) {
+        match &mut self.0 {
+            DmaTransfer::Ram { ref mut req, .. } => pi_cl_ram_read_wait(req),
+            DmaTransfer::L2 { ref mut cmd, .. } => pi_cl_dma_wait(cmd),
+        }
+    }
+}
+
+impl DmaOut {
+    // same as [DmaIn::start]
+    unsafe fn start(&mut self, remote: *mut u8, l1: *mut u8, len: usize) {
+        match &mut self.0 {
+            DmaTransfer::Ram { ref mut req, .. } => {
+                pi_cl_team_critical_enter();
                 pi_cl_ram_write(req.device(), remote, l1, len, req);
+                pi_cl_team_critical_exit();
             }
-            Self::L2 { ref mut cmd, .. } => {
+            DmaTransfer::L2 { ref mut cmd, .. } => {
                 pi_cl_dma_cmd(remote, l1, len, PiClDmaDirE::PI_CL_DMA_DIR_LOC2EXT, cmd);
             }
         }
     }
 
-    // TODO typestate
     // Safety: do not call on uninitialized requests
     unsafe fn wait(&mut self) {
-        match self {
-            Self::Ram { ref mut req, .. } if req.is_in_transfer() => pi_cl_ram_read_wait(req),
-            Self::Ram { ref mut req, .. } => pi_cl_ram_write_wait(req),
-            Self::L2 { ref mut cmd, .. } => pi_cl_dma_wait(cmd),
+        match &mut self.0 {
+            DmaTransfer::Ram { ref mut req, .. } => pi_cl_ram_write_wait(req),
+            DmaTransfer::L2 { ref mut cmd, .. } => pi_cl_dma_wait(cmd),
         }
     }
 }
 
-impl<'alloc, 'buf, 'source, const CORES: usize, const BUF_LEN: usize>
-    DmaBuf<'alloc, 'buf, 'source, CORES, BUF_LEN>
-{
-    pub const FULL_WORK_BUF_LEN: usize = BUF_LEN;
+enum Pending {
+    None,
+    In(DmaIn),
+    Out(DmaOut),
+}
 
-    fn common(
-        source: SourcePtr<'source>,
-        l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN>,
-        mut pre_fetch_dma: DmaTransfer,
-        commit_dma: DmaTransfer,
-    ) -> Self {
-        assert_eq!(BUF_LEN % CORES, 0);
+/// Transfers started by a single core without waiting for them, at most [N] in flight.
+///
+/// They are waited for in the order they were started, and all of them when dropped.
+pub(crate) struct DmaQueue<const N: usize> {
+    slots: [Pending; N],
+    started: usize,
+    waited: usize,
+    _pin: PhantomPinned,
+}
+
+impl<const N: usize> DmaQueue<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| Pending::None),
+            started: 0,
+            waited: 0,
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Start copying [len] bytes from [remote], found in [loc], to [l1]
+    ///
+    /// # Safety:
+    /// * must be called in the PULP cluster
+    /// * pointers must be valid for len bytes until the transfer is waited for
+    pub(crate) unsafe fn copy_in(
+        self: Pin<&mut Self>,
+        loc: SourceLocation,
+        remote: *mut u8,
+        l1: *mut u8,
+        len: usize,
+    ) {
+        match self.next_slot(Pending::In(DmaIn(DmaTransfer::new(loc)))) {
+            Pending::In(dma) => dma.start(remote, l1, len),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Start copying [len] bytes from [l1] to [remote], found in [loc]
+    ///
+    /// # Safety:
+    /// * same as [copy_in](Self::copy_in)
+    pub(crate) unsafe fn copy_out(
+        self: Pin<&mut Self>,
+        loc: SourceLocation,
+        remote: *mut u8,
+        l1: *mut u8,
+        len: usize,
+    ) {
+        match self.next_slot(Pending::Out(DmaOut(DmaTransfer::new(loc)))) {
+            Pending::Out(dma) => dma.start(remote, l1, len),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Number of transfers started so far, to later [wait_until](Self::wait_until) they are done
+    pub(crate) fn started(&self) -> usize {
+        self.started
+    }
+
+    /// Wait for the first [count] transfers ever started
+    pub(crate) fn wait_until(self: Pin<&mut Self>, count: usize) {
+        // SAFETY: descriptors are not moved, only waited for
+        let this = unsafe { self.get_unchecked_mut() };
+        while this.waited < count.min(this.started) {
+            this.wait_oldest();
+        }
+    }
+
+    /// Wait for every transfer started so far
+    pub(crate) fn wait_all(self: Pin<&mut Self>) {
+        let started = self.started;
+        self.wait_until(started);
+    }
+
+    // Slot for a new transfer, waiting for the oldest one if all of them are in use
+    fn next_slot(self: Pin<&mut Self>, transfer: Pending) -> &mut Pending {
+        // SAFETY: only a slot no longer in use by the DMA is overwritten
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.started - this.waited == N {
+            this.wait_oldest();
+        }
+        let slot = &mut this.slots[this.started % N];
+        *slot = transfer;
+        this.started += 1;
+        slot
+    }
+
+    fn wait_oldest(&mut self) {
+        // SAFETY: slots from [waited] to [started] hold transfers that were started
         unsafe {
-            let size = core::cmp::min(BUF_LEN * 2, source.len);
-            // initialize first buffer
-            if pi_core_id() == 0 {
-                pre_fetch_dma.transfer_in(source.ptr, l1_alloc.buf, size);
-                pre_fetch_dma.wait();
+            match &mut self.slots[self.waited % N] {
+                Pending::In(dma) => dma.wait(),
+                Pending::Out(dma) => dma.wait(),
+                Pending::None => {}
             }
-            pi_cl_team_barrier();
         }
+        self.waited += 1;
+    }
+}
 
-        Self {
-            l1_alloc,
-            pre_fetch_dma,
-            commit_dma,
-            rounds: 0,
-            counters: [0, BUF_LEN, BUF_LEN * 2],
-            last_transfer: core::cmp::min(
-                BUF_LEN,
-                source.len.checked_sub(BUF_LEN).unwrap_or_default(),
-            ),
-            work_buf_len: core::cmp::min(BUF_LEN, source.len),
-            source,
+impl<const N: usize> Drop for DmaQueue<N> {
+    fn drop(&mut self) {
+        while self.waited < self.started {
+            self.wait_oldest();
         }
     }
+}
+
+impl<'alloc, 'buf, 'source, const CORES: usize, const BUF_LEN: usize, const DEPTH: usize>
+    DmaBuf<'alloc, 'buf, 'source, CORES, BUF_LEN, DEPTH>
+{
+    pub const FULL_WORK_BUF_LEN: usize = BUF_LEN;
+    // rounds pre-fetched in addition to the one being worked on
+    const AHEAD: usize = DEPTH - 2;
 
-    /// Build a new managed L1 cluster buffer backing a ram memory allocation
+    /// Build a new managed L1 cluster buffer reading from [source] and committing to [dest],
+    /// with the slice of each round assigned to every core starting on a multiple of [align].
+    /// [source] and [dest] must have the same length and can be the same memory.
+    ///
+    /// Loads the first rounds before returning, so it must be called by every core of the team.
+    /// Fails if called on the FC or from a team of a size other than [CORES], if a location is L1
+    /// or if the lengths differ.
     ///
-    /// Safety:
-    /// * should only be called from within a PULP cluster
-    pub fn new_from_ram(
+    /// # Safety:
+    /// * locations must be correctly specified in [source_loc] and [dest_loc]
+    /// * if present, ram device pointers must be valid for the whole lifetime of the buffer
+    pub unsafe fn new(
         source: SourcePtr<'source>,
-        l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN>,
-        device: NonNull<PiDevice>,
-    ) -> Self {
-        Self::common(
+        source_loc: SourceLocation,
+        dest: SourcePtr<'source>,
+        dest_loc: SourceLocation,
+        l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN, DEPTH>,
+        align: usize,
+    ) -> Result<Self, WrapperError> {
+        if pi_is_fc() || pi_cl_team_nb_cores() != CORES {
+            return Err(WrapperError::InvalidState);
+        }
+        if matches!(source_loc, SourceLocation::L1) || matches!(dest_loc, SourceLocation::L1) {
+            return Err(WrapperError::UnsupportedLocation);
+        }
+        if source.len != dest.len || align == 0 || (BUF_LEN / CORES) % align != 0 {
+            return Err(WrapperError::InvalidLength);
+        }
+        Ok(Self::with_partition(
             source,
+            source_loc,
+            dest,
+            dest_loc,
             l1_alloc,
-            DmaTransfer::new_ram(device),
-            DmaTransfer::new_ram(device),
-        )
+            Partition::new(align, 0),
+        ))
     }
 
-    /// Build a new managed L1 cluster buffer backing a L2 memory allocation
+    /// Same as [new] with a custom partition and without checks, which are done by
+    /// [PulpWrapper](crate::PulpWrapper) on the FC before starting the cluster
     ///
-    /// Safety:
-    /// * should only be called from within a PULP cluster
-    pub fn new_from_l2(
+    /// # Safety:
+    /// * should only be called from within a PULP cluster, by every core of the team
+    /// * locations must not be L1 and lengths must match
+    pub(crate) unsafe fn with_partition(
         source: SourcePtr<'source>,
-        l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN>,
+        source_loc: SourceLocation,
+        dest: SourcePtr<'source>,
+        dest_loc: SourceLocation,
+        l1_alloc: &'buf BufAlloc<'alloc, BUF_LEN, DEPTH>,
+        partition: Partition<CORES>,
     ) -> Self {
-        Self::common(
-            source,
+        debug_assert_eq!(BUF_LEN % CORES, 0);
+        debug_assert_eq!(source.len, dest.len);
+        let mut buf = Self {
             l1_alloc,
-            DmaTransfer::new_l2(),
-            DmaTransfer::new_l2(),
-        )
+            pre_fetch_dma: core::array::from_fn(|_| DmaIn(DmaTransfer::new(source_loc))),
+            commit_dma: DmaOut(DmaTransfer::new(dest_loc)),
+            pre_fetch_pending: [false; DEPTH],
+            commit_pending: false,
+            flushed: false,
+            read_only: false,
+            rounds: 0,
+            source,
+            dest,
+            partition,
+        };
+        // Load the work buffer and the pre-fetch ones.
+        // Transfers are complete before returning, so the descriptors can still be moved.
+        match l1_alloc.layout {
+            // all at once, they are contiguous
+            BufLayout::Contiguous => {
+                let size = core::cmp::min(BUF_LEN * (Self::AHEAD + 1), buf.source.len);
+                if pi_core_id() == 0 && size > 0 {
+                    let dma: &mut DmaIn = &mut buf.pre_fetch_dma[0];
+                    dma.start(buf.source.ptr, l1_alloc.buf, size);
+                    dma.wait();
+                }
+            }
+            BufLayout::Interleaved => {
+                for round in (0..=Self::AHEAD).take_while(|round| round * BUF_LEN < buf.source.len) {
+                    if let Some((offset, len, l1)) = buf.transfer_range(round) {
+                        buf.pre_fetch_dma[round].start(buf.source.ptr.add(offset), l1, len);
+                        buf.pre_fetch_pending[round] = true;
+                    }
+                }
+                for round in 0..=Self::AHEAD {
+                    if buf.pre_fetch_pending[round] {
+                        buf.pre_fetch_dma[round].wait();
+                        buf.pre_fetch_pending[round] = false;
+                    }
+                }
+            }
+        }
+        pi_cl_team_barrier();
+        buf
+    }
+
+    /// Only read the source, without ever committing the work buffers (e.g. to compute a MAC).
+    /// The work buffers can still be used as scratch space.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Number of rounds needed to stream all the data
+    #[inline(always)]
+    pub fn total_rounds(&self) -> usize {
+        (self.source.len + BUF_LEN - 1) / BUF_LEN
+    }
+
+    /// Whether all rounds have been worked on
+    #[inline(always)]
+    pub fn is_done(&self) -> bool {
+        self.rounds >= self.total_rounds()
+    }
+
+    /// Run [f] on the slice of every round assigned to the current core, together with
+    /// its position in the source, then flush.
+    ///
+    /// Must be called by every core of the team.
+    pub fn process(
+        mut self: Pin<&mut Self>,
+        mut f: impl FnMut(InOutBuf<'_, '_, u8>, usize),
+    ) -> Result<(), WrapperError> {
+        while !self.is_done() {
+            let pos = self.rounds * BUF_LEN + self.work_offset();
+            f(self.as_mut().get_work_buf(), pos);
+            self.as_mut().advance()?;
+        }
+        self.flush()
+    }
+
+    /// Length of the data of [round]
+    #[inline(always)]
+    fn round_len(&self, round: usize) -> usize {
+        core::cmp::min(BUF_LEN, self.source.len.saturating_sub(round * BUF_LEN))
+    }
+
+    /// Shift of the slice of each core with [BufLayout::Interleaved], so that the first words
+    /// of the slices are spread across the banks
+    const SKEW: usize = {
+        let words = <Target as Platform>::L1_BANKS / CORES;
+        let skew = WORD * if words == 0 { 1 } else { words };
+        assert!(
+            (CORES - 1) * skew <= BufAlloc::<BUF_LEN, DEPTH>::PAD,
+            "too many cores for the interleaved layout"
+        );
+        skew
+    };
+
+    /// L1 address of byte [offset] of [round], as seen by the current core
+    #[inline(always)]
+    fn round_buf_ptr(&self, round: usize, offset: usize) -> *mut u8 {
+        let skew = match self.l1_alloc.layout {
+            BufLayout::Contiguous => 0,
+            BufLayout::Interleaved => unsafe { pi_core_id() * Self::SKEW },
+        };
+        unsafe {
+            self.l1_alloc
+                .buf
+                .add(round % DEPTH * self.l1_alloc.stride() + skew + offset)
+        }
+    }
+
+    /// Part of [round] moved by the current core, as offset in the data, length and L1 address:
+    /// the whole round on core 0 with [BufLayout::Contiguous], the slice of the core
+    /// with [BufLayout::Interleaved]
+    #[inline(always)]
+    fn transfer_range(&self, round: usize) -> Option<(usize, usize, *mut u8)> {
+        let core_id = unsafe { pi_core_id() };
+        let (start, len) = match self.l1_alloc.layout {
+            BufLayout::Contiguous if core_id == 0 => (0, self.round_len(round)),
+            BufLayout::Contiguous => return None,
+            BufLayout::Interleaved => self.partition.slice(self.round_len(round), core_id),
+        };
+        if len == 0 {
+            return None;
+        }
+        Some((round * BUF_LEN + start, len, self.round_buf_ptr(round, start)))
     }
 
-    /// Signal that work has completed on the current 'work' buffer
+    /// Signal that work has completed on the current 'work' buffer, commit it and move to
+    /// the next round.
     ///
-    /// Safety:
-    /// * should only be called from within a PULP cluster
+    /// Must be called by every core of the team, fails if all rounds are already done.
     #[inline]
-    pub fn advance(&mut self) {
-        self.rounds += 1;
-        let a = self.counters[0];
-        self.counters[0] = self.counters[1];
-        self.counters[1] = self.counters[2];
-        self.counters[2] = a;
-        // Only core 0 interacts with the dma
+    pub fn advance(self: Pin<&mut Self>) -> Result<(), WrapperError> {
+        if self.is_done() || self.flushed {
+            return Err(WrapperError::InvalidState);
+        }
+        // Safety: the struct is not moved out of the pinned reference
+        let this = unsafe { self.get_unchecked_mut() };
+        let done = this.rounds;
+        this.rounds += 1;
+        // Each core only waits for the transfers it started, see [transfer_range]
         // (this is unsafe only because those are FFI calls)
         unsafe {
-            let offset = (self.rounds + 1) * BUF_LEN;
-            let size = core::cmp::min(self.source.len.saturating_sub(offset), BUF_LEN);
-            if pi_core_id() == 0 {
-                if self.rounds > 1 {
-                    // wait dma completed on commit buf before using it as pre-fetch (should not actually wait in practice)
-                    self.commit_dma.wait();
-                    // wait dma completed on current work buf
-                    self.pre_fetch_dma.wait();
-                }
-
+            if this.commit_pending {
+                // wait dma completed on commit buf before using it as pre-fetch (should not actually wait in practice)
+                this.commit_dma.wait();
+                this.commit_pending = false;
+            }
+            // slices change with the length of the round, so with per-core transfers
+            // every commit of the buffer must be done before any core pre-fetches in it
+            if this.l1_alloc.layout == BufLayout::Interleaved {
                 pi_cl_team_barrier();
+            }
 
-                // start dma out (commit)
-                let commit_buf_ptr = self.get_commit_buf_ptr();
-                self.commit_dma.transfer_out(
-                    self.source.ptr.add((self.rounds - 1) * BUF_LEN),
-                    commit_buf_ptr,
-                    self.work_buf_len,
-                );
-
-                if offset < self.source.len {
-                    // start dma in (pre-fetch)
-                    let pre_fetch_buf_ptr = self.get_pre_fetch_buf_ptr();
-                    self.pre_fetch_dma.transfer_in(
-                        self.source.ptr.add(offset),
-                        pre_fetch_buf_ptr,
-                        size,
-                    );
+            // start dma in (pre-fetch) on the buffer just committed
+            let fetch = done + 1 + Self::AHEAD;
+            if fetch * BUF_LEN < this.source.len {
+                if let Some((offset, len, l1)) = this.transfer_range(fetch) {
+                    this.pre_fetch_dma[fetch % DEPTH].start(this.source.ptr.add(offset), l1, len);
+                    this.pre_fetch_pending[fetch % DEPTH] = true;
                 }
-            } else {
-                // everyone has to wait for transfers to be finished
+            }
+
+            // wait dma completed on next work buf, unless it was part of the initial transfer
+            let next = done + 1;
+            if this.pre_fetch_pending[next % DEPTH] {
+                this.pre_fetch_dma[next % DEPTH].wait();
+                this.pre_fetch_pending[next % DEPTH] = false;
+            }
+
+            // everyone has to wait for transfers to be finished, and core 0 for the work
+            // of the other cores to be done before committing the whole round
+            if this.l1_alloc.layout == BufLayout::Contiguous {
                 pi_cl_team_barrier();
             }
 
-            self.work_buf_len = self.last_transfer;
-            self.last_transfer = size;
+            // start dma out (commit)
+            if this.read_only {
+                return Ok(());
+            }
+            if let Some((offset, len, l1)) = this.transfer_range(done) {
+                this.commit_dma.start(this.dest.ptr.add(offset), l1, len);
+                this.commit_pending = true;
+            }
         }
+        Ok(())
     }
 
-    /// Finalize by flushing all local cached data upstream
+    /// Finalize by waiting for the last commit, after all rounds have been worked on.
     ///
-    /// Safety:
-    /// * must be called in the PULP cluster
-    /// * must be called after having called advance() at least once
-    pub unsafe fn flush(&mut self) {
-        if pi_core_id() == 0 {
-            self.commit_dma.wait();
+    /// Must be called by every core of the team, fails if some rounds are still to be done
+    /// or if already flushed.
+    pub fn flush(self: Pin<&mut Self>) -> Result<(), WrapperError> {
+        if !self.is_done() || self.flushed {
+            return Err(WrapperError::InvalidState);
         }
+        // Safety: the struct is not moved out of the pinned reference
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.commit_pending {
+            // each core waits for its own commit, see [transfer_range]: only core 0 starts one
+            // with [BufLayout::Contiguous], every core with a slice with [BufLayout::Interleaved]
+            unsafe { this.commit_dma.wait() };
+            this.commit_pending = false;
+        }
+        this.flushed = true;
         pi_cl_team_barrier();
+        Ok(())
     }
 
-    /// Get mutable pointers to working core buffer
+    /// Offset of the current core's slice from the beginning of the work buffer
     #[inline(always)]
-    pub fn get_work_buf(&mut self) -> InOutBuf<'_, '_, u8> {
-        let core_buf_len = (self.work_buf_len + CORES - 1) / CORES;
-        let base = core_buf_len * unsafe { pi_core_id() };
-        let len = core::cmp::min(core_buf_len, self.work_buf_len.saturating_sub(base));
-        unsafe {
-            let ptr = self.l1_alloc.buf.add(self.counters[0] + base);
-            InOutBuf::from_raw(ptr as *const u8, ptr, len)
-        }
+    pub fn work_offset(&self) -> usize {
+        let len = self.round_len(self.rounds);
+        self.partition.slice(len, unsafe { pi_core_id() }).0
     }
 
+    /// Get mutable pointers to working core buffer, empty once all rounds are done
     #[inline(always)]
-    fn get_pre_fetch_buf_ptr(&mut self) -> *mut u8 {
-        unsafe { self.l1_alloc.buf.add(self.counters[1]) }
+    pub fn get_work_buf(self: Pin<&mut Self>) -> InOutBuf<'_, '_, u8> {
+        let (base, len) = self
+            .partition
+            .slice(self.round_len(self.rounds), unsafe { pi_core_id() });
+        unsafe {
+            let ptr = self.round_buf_ptr(self.rounds, base);
+            InOutBuf::from_raw(ptr as *const u8, ptr, len)
+        }
     }
+}
 
-    #[inline(always)]
-    fn get_commit_buf_ptr(&mut self) -> *mut u8 {
-        unsafe { self.l1_alloc.buf.add(self.counters[2]) }
+impl<'alloc, 'buf, 'source, const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> Drop
+    for DmaBuf<'alloc, 'buf, 'source, CORES, BUF_LEN, DEPTH>
+{
+    fn drop(&mut self) {
+        // the DMA must be done with the descriptors before they go away
+        unsafe {
+            for (dma, pending) in self.pre_fetch_dma.iter_mut().zip(self.pre_fetch_pending) {
+                if pending {
+                    dma.wait();
+                }
+            }
+            if self.commit_pending {
+                self.commit_dma.wait();
+            }
+        }
     }
 }
@@ -0,0 +1,55 @@
+use core::alloc::AllocError;
+
+/// Errors returned by [PulpWrapper](crate::PulpWrapper) instead of panicking or aborting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapperError {
+    /// The locations are not supported in this combination
+    /// (e.g. L1 source with a dest outside L1)
+    UnsupportedLocation,
+    /// The data extends past the end of the keystream of the cipher
+    KeystreamOverflow,
+    /// Lengths of source and dest differ, or a length exceeds what the operation supports
+    InvalidLength,
+    /// Not enough L1 memory for the buffers or the cluster task
+    Alloc,
+    /// The ram device was not opened, so no transfer could be started
+    Dma,
+    /// The authentication tag did not match the data
+    TagMismatch,
+    /// A [DmaBuf](crate::DmaBuf) was used out of order or outside of the cluster team
+    /// it was built for, or too many jobs are in flight
+    InvalidState,
+}
+
+impl From<AllocError> for WrapperError {
+    fn from(_: AllocError) -> Self {
+        Self::Alloc
+    }
+}
+
+/// Summary of a completed run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunReport {
+    /// Bytes processed
+    pub len: usize,
+    /// Rounds of DMA transfers through L1, 0 if data was processed in place in L1
+    pub rounds: usize,
+}
+
+/// Error raised by each core, collected by the FC once the task completes
+pub(crate) struct CoreErrors<const CORES: usize>([Option<WrapperError>; CORES]);
+
+impl<const CORES: usize> CoreErrors<CORES> {
+    pub fn new() -> Self {
+        Self([None; CORES])
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut Option<WrapperError> {
+        self.0.as_mut_ptr()
+    }
+
+    /// First error raised, in core order
+    pub fn first(&self) -> Result<(), WrapperError> {
+        self.0.iter().find_map(|e| *e).map_or(Ok(()), Err)
+    }
+}
@@ -0,0 +1,74 @@
+use crate::*;
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+
+/// A run started by [PulpWrapper::run_async], borrowing the data, key and iv until it completes.
+///
+/// Dropping the job blocks until the cluster is done with the data.
+pub struct RunJob<'a, const CORES: usize, const BUF_LEN: usize, const DEPTH: usize = 3> {
+    // declared first so that it is dropped (i.e. waited on) before the error slots, and before
+    // the slot is given back
+    job: ClusterJob<'a, CoreData<BUF_LEN, DEPTH>, CORES>,
+    errors: Box<CoreErrors<CORES>, L2Allocator>,
+    report: RunReport,
+    _slot: JobSlot<'a>,
+    _data: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a, const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> RunJob<'a, CORES, BUF_LEN, DEPTH> {
+    /// # Safety:
+    /// * the job must not be leaked, see [Cluster::execute_fn_parallel_async]
+    pub(crate) unsafe fn start(
+        cluster: &'a Cluster<CORES>,
+        in_flight: &'a Cell<usize>,
+        f: extern "C" fn(&CoreData<BUF_LEN, DEPTH>),
+        mut data: CoreData<BUF_LEN, DEPTH>,
+        stacks: StackSize,
+        report: RunReport,
+    ) -> Result<Self, WrapperError> {
+        let slot = JobSlot::take(in_flight, PulpWrapper::<CORES, BUF_LEN, DEPTH>::MAX_JOBS)?;
+        // the cores write to the slots after the job may have moved, so they need a fixed address
+        let mut errors = Box::try_new_in(CoreErrors::new(), L2Allocator)?;
+        data.errors = errors.as_mut_ptr();
+        let job = cluster.execute_fn_parallel_async(f, data, stacks)?;
+        Ok(Self {
+            job,
+            errors,
+            report,
+            _slot: slot,
+            _data: PhantomData,
+        })
+    }
+
+    /// Whether the cluster is done with the data, without blocking
+    pub fn is_done(&self) -> bool {
+        self.job.is_done()
+    }
+
+    /// Block until the run has completed and release the data
+    pub fn wait(self) -> Result<RunReport, WrapperError> {
+        let Self { job, errors, report, .. } = self;
+        job.wait();
+        errors.first().map(|()| report)
+    }
+}
+
+/// One of the jobs that the L1 memory reserved by [PulpWrapper] allows in flight,
+/// given back when dropped
+struct JobSlot<'a>(&'a Cell<usize>);
+
+impl<'a> JobSlot<'a> {
+    fn take(in_flight: &'a Cell<usize>, max: usize) -> Result<Self, WrapperError> {
+        if in_flight.get() >= max {
+            return Err(WrapperError::InvalidState);
+        }
+        in_flight.set(in_flight.get() + 1);
+        Ok(Self(in_flight))
+    }
+}
+
+impl Drop for JobSlot<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::*;
+use cipher::IvSizeUser;
+use generic_array::ArrayLength;
+
+/// Bytes of keystream available for a key / iv pair, `None` if unlimited
+pub(crate) fn keystream_len<C: KeyIvInit + KeystreamBlock>(
+    key: &GenericArray<u8, C::KeySize>,
+    iv: &GenericArray<u8, C::IvSize>,
+) -> Option<u64> {
+    remaining_len(&C::new(key, iv))
+}
+
+/// Bytes of keystream left in [cipher], `None` if unlimited
+pub(crate) fn remaining_len<C: KeystreamBlock>(cipher: &C) -> Option<u64> {
+    cipher
+        .remaining_blocks()
+        .map(|blocks| blocks as u64 * C::BlockSize::U64)
+}
+
+/// Derives the iv of the [segment]-th keystream segment from the original one,
+/// `None` if there are no more segments.
+///
+/// Used by [StreamSession::segmented](crate::StreamSession::segmented) to continue a stream
+/// past the end of the keystream of a single iv.
+pub type DeriveIv<C> = fn(
+    &GenericArray<u8, <C as IvSizeUser>::IvSize>,
+    u64,
+) -> Option<GenericArray<u8, <C as IvSizeUser>::IvSize>>;
+
+/// Add the segment to the 32 bit little endian word of the iv starting at byte [WORD],
+/// as if the block counter carried into it.
+///
+/// E.g. `WORD = 0` for ChaCha20 (RFC 8439), where the block counter precedes the nonce,
+/// `WORD = 4` for a 32 bit little endian counter in the first bytes of the iv.
+pub fn carry_le<N: ArrayLength<u8>, const WORD: usize>(
+    iv: &GenericArray<u8, N>,
+    segment: u64,
+) -> Option<GenericArray<u8, N>> {
+    let segment = u32::try_from(segment).ok()?;
+    let mut iv = iv.clone();
+    let word = u32::from_le_bytes(iv[WORD..WORD + 4].try_into().unwrap());
+    iv[WORD..WORD + 4].copy_from_slice(&word.wrapping_add(segment).to_le_bytes());
+    Some(iv)
+}
+
+/// Same as [carry_le], for a 32 bit big endian word.
+///
+/// E.g. `WORD = 8` for a 32 bit big endian counter in the last bytes of the iv.
+pub fn carry_be<N: ArrayLength<u8>, const WORD: usize>(
+    iv: &GenericArray<u8, N>,
+    segment: u64,
+) -> Option<GenericArray<u8, N>> {
+    let segment = u32::try_from(segment).ok()?;
+    let mut iv = iv.clone();
+    let word = u32::from_be_bytes(iv[WORD..WORD + 4].try_into().unwrap());
+    iv[WORD..WORD + 4].copy_from_slice(&word.wrapping_add(segment).to_be_bytes());
+    Some(iv)
+}
@@ -3,51 +3,204 @@
 #![feature(new_uninit)]
 extern crate alloc;
 
+use cipher::inout::InOutBuf;
 use cipher::{KeyIvInit, StreamCipher, StreamCipherSeek, Unsigned};
+use core::cell::Cell;
 use core::ptr::NonNull;
 use pulp_sdk_rust::*;
 
 use generic_array::GenericArray;
 
+mod aead;
+mod batch;
+mod block;
 mod buf;
-use buf::{BufAlloc, DmaBuf, SourcePtr};
+mod error;
+mod job;
+mod keystream;
+mod mac;
+mod partition;
+#[cfg(feature = "self-test")]
+mod selftest;
+mod session;
+mod shared;
+mod uhash;
+mod xnonce;
+mod xts;
+pub use aead::{ChaCha20Ietf, GCM_IV_LEN, TAG_LEN};
+pub use batch::Job;
+pub use buf::{BufAlloc, BufLayout, DmaBuf, SourcePtr};
+use error::CoreErrors;
+pub use error::{RunReport, WrapperError};
+pub use job::RunJob;
+use keystream::{keystream_len, remaining_len};
+pub use keystream::{carry_be, carry_le, DeriveIv};
+use partition::{CoreAlign, Partition};
+pub use partition::KeystreamBlock;
+#[cfg(feature = "self-test")]
+pub use selftest::misuse;
+pub use session::StreamSession;
+pub use shared::KeySchedule;
+pub use xnonce::ExtendedNonce;
+
+/// [PulpWrapper] using all cluster cores and the default L1 buffer size of the selected platform
+pub type DefaultPulpWrapper = PulpWrapper<
+    { <Target as Platform>::CLUSTER_CORES },
+    { <Target as Platform>::DEFAULT_L1_BUFFER_LEN },
+>;
 
 /// Convenience struct for stream encryption / decryption using the PULP cluster.
 /// Supports encryption / decryption directly from ram or L2 memory and manages
-/// dma in/out autonomously.
-pub struct PulpWrapper<const CORES: usize, const BUF_LEN: usize> {
+/// dma in/out autonomously. Data already in L1 is processed in place.
+pub struct PulpWrapper<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize = 3> {
     cluster: Cluster<CORES>,
     // The correct lifetime here would be 'self if we could write it
     // As long as this is never exposed outside and we know our use does not
     // result in invalid references it's fine to use 'static
-    cluster_buffer: BufAlloc<'static, BUF_LEN>,
+    cluster_buffer: BufAlloc<'static, BUF_LEN, DEPTH>,
+    stacks: StackSize,
+    // jobs started by [run_async] that did not complete yet
+    jobs_in_flight: Cell<usize>,
 }
 
-impl<const CORES: usize, const BUF_LEN: usize> PulpWrapper<CORES, BUF_LEN> {
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    const VALID_BUF_LEN: () = assert!(BUF_LEN % CORES == 0, "BUF_LEN must be a multiple of CORES");
+
+    /// Jobs started by [run_async](Self::run_async) that can be in flight at the same time,
+    /// e.g. to double-buffer the input on the FC. L1 memory for their tasks is reserved by [new].
+    pub const MAX_JOBS: usize = 2;
+
     /// Initialize the wrapper and allocates necessary buffers in the cluster.
     /// This is to reuse allocations across calls to [run].
-    pub fn new(cluster: Cluster<CORES>) -> Self {
-        let buffer = <BufAlloc<BUF_LEN>>::new(&cluster);
-        Self {
+    ///
+    /// Fails if the buffers, together with the cores stacks and the arguments needed by
+    /// [MAX_JOBS](Self::MAX_JOBS) runs in flight, do not fit in the free L1 memory.
+    ///
+    /// Functions sharing a state built by the FC across the cores
+    /// (e.g. [run_shared](Self::run_shared), block modes and MACs) also need room in L1 for it.
+    pub fn new(cluster: Cluster<CORES>) -> Result<Self, WrapperError> {
+        Self::new_with_stacks(cluster, StackSize::DEFAULT)
+    }
+
+    /// Same as [new], but cores will run with the given stack sizes unless overridden
+    /// for some calls with [with_stacks](Self::with_stacks).
+    /// Ciphers with big states or key schedules might need more than the default.
+    pub fn new_with_stacks(cluster: Cluster<CORES>, stacks: StackSize) -> Result<Self, WrapperError> {
+        Self::new_with_layout(cluster, stacks, BufLayout::Contiguous)
+    }
+
+    /// Same as [new_with_stacks], with the L1 buffers placed according to [layout]
+    pub fn new_with_layout(
+        cluster: Cluster<CORES>,
+        stacks: StackSize,
+        layout: BufLayout,
+    ) -> Result<Self, WrapperError> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_BUF_LEN;
+        let required = <BufAlloc<BUF_LEN, DEPTH>>::size(layout)
+            + Self::MAX_JOBS * <Cluster<CORES>>::execute_l1_overhead::<CoreData<BUF_LEN, DEPTH>>(stacks);
+        if required > cluster.l1_free() {
+            return Err(WrapperError::Alloc);
+        }
+        let buffer = <BufAlloc<BUF_LEN, DEPTH>>::new_with_layout(&cluster, layout)?;
+        Ok(Self {
             cluster_buffer: unsafe {
-                core::mem::transmute::<BufAlloc<'_, BUF_LEN>, BufAlloc<'static, BUF_LEN>>(buffer)
+                core::mem::transmute::<BufAlloc<'_, BUF_LEN, DEPTH>, BufAlloc<'static, BUF_LEN, DEPTH>>(buffer)
             },
             cluster,
-        }
+            stacks,
+            jobs_in_flight: Cell::new(0),
+        })
+    }
+
+    /// Run [f] with the cores using [stacks] instead of the stack sizes the wrapper was built with,
+    /// e.g. for the calls with a cipher that needs bigger stacks than the others.
+    ///
+    /// L1 memory was only reserved for the stack sizes given to [new_with_stacks]: with bigger
+    /// ones, calls in [f] fail with [WrapperError::Alloc] if they do not fit in the free L1 memory.
+    pub fn with_stacks<R>(&mut self, stacks: StackSize, f: impl FnOnce(&mut Self) -> R) -> R {
+        let default = core::mem::replace(&mut self.stacks, stacks);
+        let res = f(self);
+        self.stacks = default;
+        res
+    }
+
+    /// Stack usage of each core during the last call to [run]
+    #[cfg(feature = "stack-check")]
+    pub fn stack_usage(&self) -> &[StackUsage; CORES] {
+        self.cluster.stack_usage()
+    }
+
+    /// Performance counters of each core during the last call to [run]
+    #[cfg(feature = "perf-counters")]
+    pub fn perf_counters(&self) -> &[PerfCounters; CORES] {
+        self.cluster.perf_counters()
     }
 
     /// Encrypt / decrypt data in [source] with given key and iv
     ///
+    /// Locations, lengths and the keystream range are checked before the cluster is started,
+    /// so that on error [source] is left untouched.
+    ///
     /// # Safety:
     /// * source location must be correctly specified in [loc]
     /// * if present, ram device pointer must be valid to read for the whole duration
-    pub unsafe fn run<C: StreamCipher + StreamCipherSeek + KeyIvInit>(
+    pub unsafe fn run<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
         &mut self,
         source: &mut [u8],
         key: &GenericArray<u8, C::KeySize>,
         iv: &GenericArray<u8, C::IvSize>,
         loc: SourceLocation,
-    ) {
+    ) -> Result<RunReport, WrapperError> {
+        self.run_at::<C>(source, key, iv, loc, 0)
+    }
+
+    /// Same as [run], but the keystream starts at byte [stream_offset]
+    ///
+    /// # Safety:
+    /// * same as [run]
+    unsafe fn run_at<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        loc: SourceLocation,
+        stream_offset: u64,
+    ) -> Result<RunReport, WrapperError> {
+        let report = Self::check::<C>(key, iv, source.len(), loc, loc, stream_offset)?;
+        let mut data = CoreData::new(
+            source.as_mut_ptr(),
+            source.len(),
+            &self.cluster_buffer,
+            key.as_ptr(),
+            iv.as_ptr(),
+            loc,
+        );
+        data.stream_offset = stream_offset;
+        self.execute(Self::entry_point::<C>, data)?;
+        Ok(report)
+    }
+
+    /// Same as [run], but returns as soon as the cluster task is queued, so that the FC can
+    /// prepare the next input in the meantime.
+    ///
+    /// Jobs run one after the other in the order they were started: with two buffers,
+    /// the FC can refill one while the cluster processes the other.
+    /// Fails with [WrapperError::InvalidState] if [MAX_JOBS](Self::MAX_JOBS) jobs are
+    /// already in flight.
+    ///
+    /// # Safety:
+    /// * same as [run]
+    /// * the returned job must not be leaked (e.g. with `core::mem::forget`), as the cluster
+    ///   keeps using [source], [key] and [iv] until it completes
+    pub unsafe fn run_async<'a, C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        &'a self,
+        source: &'a mut [u8],
+        key: &'a GenericArray<u8, C::KeySize>,
+        iv: &'a GenericArray<u8, C::IvSize>,
+        loc: SourceLocation,
+    ) -> Result<RunJob<'a, CORES, BUF_LEN, DEPTH>, WrapperError> {
+        let report = Self::check::<C>(key, iv, source.len(), loc, loc, 0)?;
         let data = CoreData::new(
             source.as_mut_ptr(),
             source.len(),
@@ -56,85 +209,272 @@ impl<const CORES: usize, const BUF_LEN: usize> PulpWrapper<CORES, BUF_LEN> {
             iv.as_ptr(),
             loc,
         );
-        self.cluster.execute_fn_parallel(Self::entry_point::<C>, data);
+        RunJob::start(
+            &self.cluster,
+            &self.jobs_in_flight,
+            Self::entry_point::<C>,
+            data,
+            self.stacks,
+            report,
+        )
     }
 
-    extern "C" fn entry_point<C: StreamCipher + StreamCipherSeek + KeyIvInit>(
-        data: &CoreData<BUF_LEN>,
-    ) {
-        unsafe {
-            let CoreData {
-                key,
-                iv,
-                source,
-                len,
-                l1_alloc,
-                loc,
-            } = *data;
-            let key = GenericArray::from_slice(core::slice::from_raw_parts(key, C::KeySize::USIZE));
-            let iv = GenericArray::from_slice(core::slice::from_raw_parts(iv, C::IvSize::USIZE));
-
-            // any lifetime will do as BufAlloc is owned by PulpWrapper
-            let l1_alloc = &*l1_alloc;
-            let source = SourcePtr::from_raw_parts(source, len);
-
-            let mut cipher = C::new(key, iv);
-            let core_id = pi_core_id();
-
-            // To fit all data in L1 cache, we split input in rounds.
-            let mut buf = match loc {
-                SourceLocation::L2 => <DmaBuf<CORES, BUF_LEN>>::new_from_l2(source, l1_alloc),
-                SourceLocation::Ram(device) => {
-                    <DmaBuf<CORES, BUF_LEN>>::new_from_ram(source, l1_alloc, device)
-                }
-                _ => panic!("unsupported"),
-            };
-            // If the cipher is producing the keystream in incremental blocks,
-            // it's extremely important for efficiency that round_buf_len / cores is a multiple of the block size
-            let round_buf_len = <DmaBuf<CORES, BUF_LEN>>::FULL_WORK_BUF_LEN;
-            debug_assert_eq!(round_buf_len % CORES, 0);
-            let full_rounds = len / round_buf_len;
-            let base = core_id * (round_buf_len / CORES);
-            let mut past = 0;
-
-            for _ in 0..full_rounds {
-                cipher.seek(base + past);
-                cipher.apply_keystream_inout(buf.get_work_buf());
-                past += round_buf_len;
-                buf.advance();
+    /// Start encrypting / decrypting a stream that will be provided in pieces
+    pub fn session<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        &mut self,
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+    ) -> StreamSession<'_, C, CORES, BUF_LEN, DEPTH> {
+        StreamSession::new(self, key, iv)
+    }
+
+    /// Encrypt / decrypt data in [source] with given key and iv, writing the result to [dest]
+    /// and leaving [source] untouched.
+    ///
+    /// # Safety:
+    /// * source and dest locations must be correctly specified in [source_loc] and [dest_loc]
+    /// * if present, ram device pointers must be valid to read for the whole duration
+    /// * source and dest must not overlap
+    pub unsafe fn run_into<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        &mut self,
+        source: &[u8],
+        source_loc: SourceLocation,
+        dest: &mut [u8],
+        dest_loc: SourceLocation,
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+    ) -> Result<RunReport, WrapperError> {
+        if source.len() != dest.len() {
+            return Err(WrapperError::InvalidLength);
+        }
+        let report = Self::check::<C>(key, iv, source.len(), source_loc, dest_loc, 0)?;
+        let mut data = CoreData::new(
+            // only ever read through this pointer
+            source.as_ptr() as *mut u8,
+            source.len(),
+            &self.cluster_buffer,
+            key.as_ptr(),
+            iv.as_ptr(),
+            source_loc,
+        );
+        data.dest = dest.as_mut_ptr();
+        data.dest_loc = dest_loc;
+        self.execute(Self::entry_point::<C>, data)?;
+        Ok(report)
+    }
+
+    /// Validate a run before starting the cluster, so that the cores do not fail half-way
+    fn check<C: KeyIvInit + KeystreamBlock>(
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        len: usize,
+        loc: SourceLocation,
+        dest_loc: SourceLocation,
+        stream_offset: u64,
+    ) -> Result<RunReport, WrapperError> {
+        Self::check_keystream(keystream_len::<C>(key, iv), len, loc, dest_loc, stream_offset)
+    }
+
+    /// Same as [check], with the keystream length ([None] if unlimited) of a cipher already built
+    fn check_keystream(
+        keystream: Option<u64>,
+        len: usize,
+        loc: SourceLocation,
+        dest_loc: SourceLocation,
+        stream_offset: u64,
+    ) -> Result<RunReport, WrapperError> {
+        loc.check()?;
+        dest_loc.check()?;
+        let in_l1 = matches!(loc, SourceLocation::L1);
+        // there's no DMA between two L1 buffers
+        if in_l1 != matches!(dest_loc, SourceLocation::L1) {
+            return Err(WrapperError::UnsupportedLocation);
+        }
+        // cores seek to byte positions
+        let end = stream_offset
+            .checked_add(len as u64)
+            .ok_or(WrapperError::KeystreamOverflow)?;
+        // the whole range must be covered by the keystream, before the block counter wraps
+        if let Some(max) = keystream {
+            if end > max {
+                return Err(WrapperError::KeystreamOverflow);
             }
+        }
+        Ok(RunReport {
+            len,
+            rounds: if in_l1 { 0 } else { (len + BUF_LEN - 1) / BUF_LEN },
+        })
+    }
+
+    /// Run [f] on every core and collect the errors they raised
+    fn execute(
+        &mut self,
+        f: extern "C" fn(&CoreData<BUF_LEN, DEPTH>),
+        mut data: CoreData<BUF_LEN, DEPTH>,
+    ) -> Result<(), WrapperError> {
+        let mut errors = CoreErrors::<CORES>::new();
+        data.errors = errors.as_mut_ptr();
+        self.cluster.execute_fn_parallel_with_stacks(f, data, self.stacks)?;
+        errors.first()
+    }
+
+    extern "C" fn entry_point<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+    ) {
+        unsafe { Self::apply_keystream(data, data.cipher::<C>()) }
+    }
 
-            // handle remaining buffer
-            if len > past {
-                let base = (((len - past) + CORES - 1) / CORES) * core_id;
-                cipher.seek(base + past);
-                cipher.apply_keystream_inout(buf.get_work_buf());
-                buf.advance();
+    /// Apply the keystream of [cipher] to the slices of the source assigned to the current core
+    ///
+    /// # Safety:
+    /// * must be called on every core of the cluster
+    unsafe fn apply_keystream<C: StreamCipher + StreamCipherSeek + KeystreamBlock>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+        mut cipher: C,
+    ) {
+        let stream_offset = data.stream_offset;
+        let partition = Partition::new(CoreAlign::<C, CORES, BUF_LEN, 1>::VALUE, stream_offset);
+        Self::for_each_chunk(data, partition, |chunk, pos| {
+            // keep going on error, the other cores still need this one for the DMA rounds
+            if cipher.try_seek(stream_offset + pos as u64).is_err() {
+                data.raise(WrapperError::KeystreamOverflow);
+                return;
             }
+            cipher.apply_keystream_inout(chunk);
+        });
+    }
+
+    /// Stream the source through L1 and call [f] on the slice of each round
+    /// assigned to the current core, together with its offset in the source.
+    /// [f] is called on every core for every round, even if its slice is empty.
+    ///
+    /// # Safety:
+    /// * must be called on every core of the cluster
+    unsafe fn for_each_chunk(
+        data: &CoreData<BUF_LEN, DEPTH>,
+        partition: Partition<CORES>,
+        f: impl FnMut(InOutBuf<'_, '_, u8>, usize),
+    ) {
+        let CoreData {
+            source,
+            len,
+            l1_alloc,
+            loc,
+            dest,
+            dest_loc,
+            ..
+        } = *data;
+        if len == 0 {
+            return;
+        }
+
+        if let (SourceLocation::L1, SourceLocation::L1) = (loc, dest_loc) {
+            Self::for_each_chunk_l1(source, dest, len, partition, f);
+            return;
+        }
+
+        // any lifetime will do as BufAlloc is owned by PulpWrapper
+        let l1_alloc = &*l1_alloc;
+        let source = SourcePtr::from_raw_parts(source, len);
+        let dest = SourcePtr::from_raw_parts(dest, len);
+
+        // To fit all data in L1 cache, we split input in rounds.
+        // Rounds start on a block boundary as round_buf_len / cores is a multiple of the block size
+        let buf = <DmaBuf<CORES, BUF_LEN, DEPTH>>::with_partition(source, loc, dest, dest_loc, l1_alloc, partition);
+        if let Err(err) = core::pin::pin!(buf).process(f) {
+            data.raise(err);
+        }
+    }
+
+    /// Same as [for_each_chunk] for a source that is only read (e.g. to compute a MAC):
+    /// [f] gets the slice of each round as a shared slice, and nothing is written back.
+    ///
+    /// # Safety:
+    /// * must be called on every core of the cluster
+    unsafe fn for_each_chunk_read_only(
+        data: &CoreData<BUF_LEN, DEPTH>,
+        partition: Partition<CORES>,
+        mut f: impl FnMut(&[u8], usize),
+    ) {
+        let CoreData {
+            source,
+            len,
+            l1_alloc,
+            loc,
+            ..
+        } = *data;
+        if len == 0 {
+            return;
+        }
 
-            buf.flush();
+        if let SourceLocation::L1 = loc {
+            // called even on an empty slice, as with DMA rounds
+            let (base, core_len) = partition.slice(len, pi_core_id());
+            f(core::slice::from_raw_parts(source.add(base), core_len), base);
+            return;
         }
+
+        // any lifetime will do as BufAlloc is owned by PulpWrapper
+        let l1_alloc = &*l1_alloc;
+        let source = || SourcePtr::from_raw_parts(source, len);
+        // work buffers are never committed, so the source is never written through dest
+        let buf = <DmaBuf<CORES, BUF_LEN, DEPTH>>::with_partition(source(), loc, source(), loc, l1_alloc, partition);
+        if let Err(err) = core::pin::pin!(buf.read_only()).process(|chunk, pos| f(chunk.get_in(), pos)) {
+            data.raise(err);
+        }
+    }
+
+    /// Data already in L1 is split across cores and processed in place, without DMA
+    ///
+    /// # Safety:
+    /// * source and dest must be valid for len bytes and either equal or non-overlapping
+    #[inline(always)]
+    unsafe fn for_each_chunk_l1(
+        source: *mut u8,
+        dest: *mut u8,
+        len: usize,
+        partition: Partition<CORES>,
+        mut f: impl FnMut(InOutBuf<'_, '_, u8>, usize),
+    ) {
+        // called even on an empty slice, as with DMA rounds, so that [f] can synchronize the cores
+        let (base, core_len) = partition.slice(len, pi_core_id());
+        f(
+            InOutBuf::from_raw(source.add(base) as *const u8, dest.add(base), core_len),
+            base,
+        );
     }
 }
 
-struct CoreData<const BUF_LEN: usize> {
+#[derive(Clone, Copy)]
+struct CoreData<const BUF_LEN: usize, const DEPTH: usize> {
     source: *mut u8,
     len: usize,
-    l1_alloc: *const BufAlloc<'static, BUF_LEN>,
+    l1_alloc: *const BufAlloc<'static, BUF_LEN, DEPTH>,
     key: *const u8,
     iv: *const u8,
     loc: SourceLocation,
+    // where the result is written, same as source for in place operations
+    dest: *mut u8,
+    dest_loc: SourceLocation,
+    // position in the keystream of the first byte of source
+    stream_offset: u64,
+    // state shared by all cores, specific to the mode (e.g. HashCtx, batch jobs)
+    ctx: *const cty::c_void,
+    // cipher built once by the FC and cloned by every core, null if each core builds its own
+    state: *const cty::c_void,
+    // one slot per core, see [CoreErrors]
+    errors: *mut Option<WrapperError>,
 }
 
 // This is not safe in general but we promise we won't abuse it
-unsafe impl<const BUF_LEN: usize> Send for CoreData<BUF_LEN> {}
-unsafe impl<const BUF_LEN: usize> Sync for CoreData<BUF_LEN> {}
+unsafe impl<const BUF_LEN: usize, const DEPTH: usize> Send for CoreData<BUF_LEN, DEPTH> {}
+unsafe impl<const BUF_LEN: usize, const DEPTH: usize> Sync for CoreData<BUF_LEN, DEPTH> {}
 
-impl<const BUF_LEN: usize> CoreData<BUF_LEN> {
+impl<const BUF_LEN: usize, const DEPTH: usize> CoreData<BUF_LEN, DEPTH> {
     fn new(
         source: *mut u8,
         len: usize,
-        l1_alloc: *const BufAlloc<'static, BUF_LEN>,
+        l1_alloc: *const BufAlloc<'static, BUF_LEN, DEPTH>,
         key: *const u8,
         iv: *const u8,
         loc: SourceLocation,
@@ -145,9 +485,52 @@ impl<const BUF_LEN: usize> CoreData<BUF_LEN> {
             l1_alloc,
             key,
             iv,
-            loc
+            loc,
+            dest: source,
+            dest_loc: loc,
+            stream_offset: 0,
+            ctx: core::ptr::null(),
+            state: core::ptr::null(),
+            errors: core::ptr::null_mut(),
         }
     }
+
+    /// Report an error to the FC, the first one raised by each core is kept
+    ///
+    /// # Safety:
+    /// * must be called on a cluster core, during [PulpWrapper::execute]
+    unsafe fn raise(&self, err: WrapperError) {
+        let slot = &mut *self.errors.add(pi_core_id());
+        if slot.is_none() {
+            *slot = Some(err);
+        }
+    }
+
+    /// Initialize a new cipher instance with the key and iv
+    ///
+    /// # Safety:
+    /// * key and iv must be valid to read for the key and iv size of C
+    unsafe fn cipher<C: KeyIvInit>(&self) -> C {
+        let key = GenericArray::from_slice(core::slice::from_raw_parts(self.key, C::KeySize::USIZE));
+        let iv = GenericArray::from_slice(core::slice::from_raw_parts(self.iv, C::IvSize::USIZE));
+        C::new(key, iv)
+    }
+
+    /// Clone of the cipher shared by all cores
+    ///
+    /// # Safety:
+    /// * state must point to an instance of C
+    unsafe fn shared_cipher<C: Clone>(&self) -> C {
+        self.shared_state::<C>().clone()
+    }
+
+    /// State built once by the FC, read in place by every core
+    ///
+    /// # Safety:
+    /// * state must point to an instance of S
+    unsafe fn shared_state<S>(&self) -> &S {
+        &*(self.state as *const S)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -156,3 +539,13 @@ pub enum SourceLocation {
     L2,
     Ram(NonNull<PiDevice>),
 }
+
+impl SourceLocation {
+    /// Whether data can be transferred from / to this location, i.e. its ram device is open
+    fn check(&self) -> Result<(), WrapperError> {
+        match self {
+            Self::Ram(device) if !unsafe { device.as_ref() }.is_open() => Err(WrapperError::Dma),
+            _ => Ok(()),
+        }
+    }
+}
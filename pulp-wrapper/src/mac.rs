@@ -0,0 +1,268 @@
+use crate::uhash::{Field, HashCtx, Poly1305, BLOCK_LEN};
+use crate::*;
+use cipher::consts::U16;
+use cipher::{BlockEncrypt, BlockSizeUser, KeyInit};
+use digest::{Mac, Output};
+
+/// Blocks encrypted at once by each core for PMAC
+const PMAC_PAR_BLOCKS: usize = 8;
+
+/// The last block of the data (1 to 16 bytes, none if the data is empty) is finished by the FC,
+/// while the cores process the blocks before it
+struct LastBlock {
+    // bytes before the last block
+    body_len: usize,
+    block: *mut [u8; BLOCK_LEN],
+    len: usize,
+}
+
+impl LastBlock {
+    fn new(len: usize, block: &mut [u8; BLOCK_LEN]) -> Self {
+        let last_len = if len == 0 { 0 } else { (len - 1) % BLOCK_LEN + 1 };
+        Self {
+            body_len: len - last_len,
+            block,
+            len: last_len,
+        }
+    }
+
+    /// Part of [chunk], found at offset [pos] in the data, before the last block,
+    /// which is copied aside if the chunk contains it
+    ///
+    /// # Safety:
+    /// * block must be valid to write, and only one chunk can contain the last block
+    unsafe fn split<'a>(&self, chunk: &'a [u8], pos: usize) -> &'a [u8] {
+        let (body, last) = chunk.split_at(chunk.len().min(self.body_len.saturating_sub(pos)));
+        if !last.is_empty() {
+            (&mut *self.block)[..last.len()].copy_from_slice(last);
+        }
+        body
+    }
+}
+
+/// State shared by all cores to compute a MAC in parallel
+struct MacCtx<S> {
+    // specific to the MAC, including where each core stores its partial result
+    state: S,
+    last: LastBlock,
+}
+
+/// PMAC (PMAC1) with the key of the block cipher expanded once
+#[derive(Clone)]
+struct Pmac<B> {
+    cipher: B,
+    // L * x^i for every bit of a block index
+    l: [u128; usize::BITS as usize],
+    // L * x^-1
+    l_inv: u128,
+}
+
+impl<B: BlockEncrypt + BlockSizeUser<BlockSize = U16>> Pmac<B> {
+    fn new(cipher: B) -> Self {
+        let mut l = GenericArray::default();
+        cipher.encrypt_block(&mut l);
+        let l = u128::from_be_bytes(l.into());
+        let mut powers = [l; usize::BITS as usize];
+        for i in 1..powers.len() {
+            powers[i] = (powers[i - 1] << 1) ^ ((powers[i - 1] >> 127) * 0x87);
+        }
+        Self {
+            cipher,
+            l: powers,
+            l_inv: (l >> 1) ^ ((l & 1) * ((1 << 127) | 0x43)),
+        }
+    }
+
+    /// Offset of block [index] (counting from 1): the L values selected by the Gray code of the index
+    fn offset(&self, index: usize) -> u128 {
+        let gray = index ^ (index >> 1);
+        (0..usize::BITS as usize)
+            .filter(|bit| (gray >> bit) & 1 != 0)
+            .fold(0, |offset, bit| offset ^ self.l[bit])
+    }
+
+    /// Sum of the encrypted blocks in [blocks], found at offset [pos] in the data
+    fn partial(&self, blocks: &[u8], pos: usize) -> u128 {
+        let mut index = pos / BLOCK_LEN + 1;
+        let mut offset = self.offset(index);
+        let mut sum = 0;
+        let mut buf = [GenericArray::<u8, U16>::default(); PMAC_PAR_BLOCKS];
+        for batch in blocks.chunks(PMAC_PAR_BLOCKS * BLOCK_LEN) {
+            let n = batch.len() / BLOCK_LEN;
+            for (block, m) in buf.iter_mut().zip(batch.chunks_exact(BLOCK_LEN)) {
+                *block = (u128::from_be_bytes(m.try_into().unwrap()) ^ offset).to_be_bytes().into();
+                index += 1;
+                offset ^= self.l[index.trailing_zeros() as usize];
+            }
+            self.cipher.encrypt_blocks(&mut buf[..n]);
+            sum = buf[..n].iter().fold(sum, |sum, block| sum ^ u128::from_be_bytes((*block).into()));
+        }
+        sum
+    }
+
+    /// Tag from the sum of the blocks before [last]
+    fn finalize(&self, sum: u128, last: &[u8]) -> [u8; TAG_LEN] {
+        let last = match <[u8; BLOCK_LEN]>::try_from(last) {
+            Ok(block) => u128::from_be_bytes(block) ^ self.l_inv,
+            Err(_) => {
+                let mut padded = [0; BLOCK_LEN];
+                padded[..last.len()].copy_from_slice(last);
+                padded[last.len()] = 0x80;
+                u128::from_be_bytes(padded)
+            }
+        };
+        let mut tag = GenericArray::from((sum ^ last).to_be_bytes());
+        self.cipher.encrypt_block(&mut tag);
+        tag.into()
+    }
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    pub(crate) const MAC_ALIGN: usize = {
+        assert!(
+            (BUF_LEN / CORES) % BLOCK_LEN == 0,
+            "BUF_LEN / CORES must be a multiple of the MAC block size"
+        );
+        BLOCK_LEN
+    };
+
+    /// Compute the Poly1305 tag of [source] with the one-time [key] (`r || s`).
+    /// The data is only read, and each core hashes its part while it's in L1.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn poly1305(
+        &mut self,
+        source: &[u8],
+        key: &[u8; 32],
+        loc: SourceLocation,
+    ) -> Result<[u8; TAG_LEN], WrapperError> {
+        let (r, s) = key.split_at(16);
+        let r = Poly1305::from_key(r.try_into().unwrap());
+        let mut last_block = [0; BLOCK_LEN];
+        let mut partials = [Poly1305::ZERO; CORES];
+        let last = LastBlock::new(source.len(), &mut last_block);
+        let ctx = MacCtx {
+            state: HashCtx::new(r, last.body_len, partials.as_mut_ptr()),
+            last,
+        };
+        let data = self.read_only_data(source, loc, &ctx as *const _ as *const cty::c_void)?;
+        self.execute(Self::poly1305_entry_point, data)?;
+
+        let mut acc = partials.iter().fold(Poly1305::ZERO, |acc, partial| acc.add(partial));
+        if ctx.last.len > 0 {
+            acc = acc.add(&Poly1305::from_last_block(&last_block[..ctx.last.len]).mul(&r));
+        }
+        Ok(acc.finalize(s.try_into().unwrap()))
+    }
+
+    /// Compute the PMAC tag of [source] with the block cipher `B`.
+    /// The data is only read, and each core encrypts its part while it's in L1.
+    ///
+    /// # Safety:
+    /// * same as [poly1305](Self::poly1305)
+    pub unsafe fn pmac<B>(
+        &mut self,
+        source: &[u8],
+        key: &GenericArray<u8, B::KeySize>,
+        loc: SourceLocation,
+    ) -> Result<[u8; TAG_LEN], WrapperError>
+    where
+        B: BlockEncrypt + BlockSizeUser<BlockSize = U16> + KeyInit + Clone,
+    {
+        let pmac = Pmac::new(B::new(key));
+        let mut last_block = [0; BLOCK_LEN];
+        let mut partials = [0u128; CORES];
+        let ctx = MacCtx {
+            state: partials.as_mut_ptr(),
+            last: LastBlock::new(source.len(), &mut last_block),
+        };
+        let data = self.read_only_data(source, loc, &ctx as *const _ as *const cty::c_void)?;
+        self.execute_shared(Self::pmac_entry_point::<B>, data, &pmac)?;
+
+        let sum = partials.iter().fold(0, |sum, partial| sum ^ partial);
+        Ok(pmac.finalize(sum, &last_block[..ctx.last.len]))
+    }
+
+    /// Compute the tag of [source] with a MAC that can't be split across cores
+    /// (e.g. `cmac::Cmac<aes::Aes128>`). The data is only read, by the first core,
+    /// while the next rounds are pre-fetched.
+    ///
+    /// # Safety:
+    /// * same as [poly1305](Self::poly1305)
+    pub unsafe fn mac_serial<M: Mac + KeyInit + Clone>(
+        &mut self,
+        source: &[u8],
+        key: &GenericArray<u8, M::KeySize>,
+        loc: SourceLocation,
+    ) -> Result<Output<M>, WrapperError> {
+        let mut tag = Output::<M>::default();
+        let data = self.read_only_data(source, loc, &mut tag as *mut Output<M> as *const cty::c_void)?;
+        self.execute_shared(Self::mac_serial_entry_point::<M>, data, &<M as KeyInit>::new(key))?;
+        Ok(tag)
+    }
+
+    /// Core data to stream [source] through L1, see [for_each_chunk_read_only](Self::for_each_chunk_read_only)
+    pub(crate) fn read_only_data(
+        &self,
+        source: &[u8],
+        loc: SourceLocation,
+        ctx: *const cty::c_void,
+    ) -> Result<CoreData<BUF_LEN, DEPTH>, WrapperError> {
+        Self::check_keystream(None, source.len(), loc, loc, 0)?;
+        let mut data = CoreData::new(
+            // only ever read through this pointer
+            source.as_ptr() as *mut u8,
+            source.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            loc,
+        );
+        data.ctx = ctx;
+        Ok(data)
+    }
+
+    extern "C" fn poly1305_entry_point(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let ctx = &*(data.ctx as *const MacCtx<HashCtx<Poly1305>>);
+            let mut partial = Poly1305::ZERO;
+            Self::for_each_chunk_read_only(data, Partition::new(Self::MAC_ALIGN, 0), |chunk, pos| {
+                let body = ctx.last.split(chunk, pos);
+                partial = partial.add(&ctx.state.partial(body, pos));
+            });
+            ctx.state.set_partial(pi_core_id(), partial);
+        }
+    }
+
+    extern "C" fn pmac_entry_point<B: BlockEncrypt + BlockSizeUser<BlockSize = U16>>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+    ) {
+        unsafe {
+            let ctx = &*(data.ctx as *const MacCtx<*mut u128>);
+            let pmac = data.shared_state::<Pmac<B>>();
+            let mut sum = 0;
+            Self::for_each_chunk_read_only(data, Partition::new(Self::MAC_ALIGN, 0), |chunk, pos| {
+                sum ^= pmac.partial(ctx.last.split(chunk, pos), pos);
+            });
+            *ctx.state.add(pi_core_id()) = sum;
+        }
+    }
+
+    extern "C" fn mac_serial_entry_point<M: Mac + Clone>(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let mut mac = (pi_core_id() == 0).then(|| data.shared_cipher::<M>());
+            // every round goes entirely to the first core
+            let partition = Partition::new(core::cmp::max(data.len, BUF_LEN), 0);
+            Self::for_each_chunk_read_only(data, partition, |chunk, _| {
+                if let Some(mac) = &mut mac {
+                    mac.update(chunk);
+                }
+            });
+            if let Some(mac) = mac {
+                *(data.ctx as *mut Output<M>) = mac.finalize().into_bytes();
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use cipher::typenum::{IsLess, Le, NonZero, U256};
+use cipher::{BlockSizeUser, StreamCipherCore, StreamCipherCoreWrapper, Unsigned};
+use core::marker::PhantomData;
+
+/// Stream ciphers producing the keystream one block at a time.
+///
+/// Work is split across cores on block boundaries, so that every core can seek
+/// directly to the start of a block instead of generating and discarding a partial one.
+pub trait KeystreamBlock {
+    type BlockSize: Unsigned;
+
+    /// Blocks of keystream left before the block counter overflows, `None` if unlimited
+    fn remaining_blocks(&self) -> Option<usize>;
+}
+
+impl<T: StreamCipherCore> KeystreamBlock for StreamCipherCoreWrapper<T>
+where
+    T::BlockSize: IsLess<U256>,
+    Le<T::BlockSize, U256>: NonZero,
+{
+    type BlockSize = <T as BlockSizeUser>::BlockSize;
+
+    fn remaining_blocks(&self) -> Option<usize> {
+        self.get_core().remaining_blocks()
+    }
+}
+
+const fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+const fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+/// Alignment of the slices assigned to each core when streaming with `C`,
+/// also satisfying the additional alignment [ALIGN] (e.g. the block of a MAC).
+pub(crate) struct CoreAlign<C, const CORES: usize, const BUF_LEN: usize, const ALIGN: usize>(
+    PhantomData<C>,
+);
+
+impl<C: KeystreamBlock, const CORES: usize, const BUF_LEN: usize, const ALIGN: usize>
+    CoreAlign<C, CORES, BUF_LEN, ALIGN>
+{
+    pub const VALUE: usize = {
+        let align = lcm(C::BlockSize::USIZE, ALIGN);
+        assert!(
+            (BUF_LEN / CORES) % align == 0,
+            "BUF_LEN / CORES must be a multiple of the cipher block size"
+        );
+        align
+    };
+}
+
+/// Alignment of the slices assigned to each core when processing whole blocks of the
+/// block cipher `B` (e.g. ECB, CBC decryption).
+pub(crate) struct BlockAlign<B, const CORES: usize, const BUF_LEN: usize>(PhantomData<B>);
+
+impl<B: BlockSizeUser, const CORES: usize, const BUF_LEN: usize> BlockAlign<B, CORES, BUF_LEN> {
+    pub const VALUE: usize = {
+        let align = B::BlockSize::USIZE;
+        assert!(
+            (BUF_LEN / CORES) % align == 0,
+            "BUF_LEN / CORES must be a multiple of the cipher block size"
+        );
+        align
+    };
+}
+
+/// How a buffer is split across cores.
+///
+/// Every core except the first starts on a position of the keystream multiple of [align]
+/// (the first one absorbs the misaligned head, if any), and every core except the
+/// last gets the same number of aligned bytes.
+#[derive(Clone, Copy)]
+pub(crate) struct Partition<const CORES: usize> {
+    align: usize,
+    // bytes before the first aligned position of the keystream
+    head: usize,
+}
+
+impl<const CORES: usize> Partition<CORES> {
+    /// [stream_offset] is the keystream position of the first byte of the data
+    pub const fn new(align: usize, stream_offset: u64) -> Self {
+        Self {
+            align,
+            head: (align - (stream_offset % align as u64) as usize) % align,
+        }
+    }
+
+    #[inline(always)]
+    fn start(&self, len: usize, core_len: usize, core_id: usize) -> usize {
+        if core_id == 0 {
+            0
+        } else {
+            core::cmp::min(len, core_len * core_id + self.head)
+        }
+    }
+
+    /// Offset and length of the slice of a buffer of [len] bytes assigned to [core_id]
+    #[inline(always)]
+    pub fn slice(&self, len: usize, core_id: usize) -> (usize, usize) {
+        let core_len = (len + CORES - 1) / CORES;
+        let core_len = (core_len + self.align - 1) / self.align * self.align;
+        let start = self.start(len, core_len, core_id);
+        let end = if core_id == CORES - 1 {
+            len
+        } else {
+            self.start(len, core_len, core_id + 1)
+        };
+        (start, end - start)
+    }
+}
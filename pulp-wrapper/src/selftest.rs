@@ -0,0 +1,113 @@
+use crate::*;
+use core::pin::{pin, Pin};
+
+/// Misuses of a [DmaBuf] checked by [PulpWrapper::check_dma_misuse], as bits of its result
+pub mod misuse {
+    /// [DmaBuf::new] on the FC
+    pub const NEW_ON_FC: u32 = 1 << 0;
+    /// [DmaBuf::new] from a team of a size other than the one of the buffer
+    pub const NEW_TEAM_SIZE: u32 = 1 << 1;
+    /// [DmaBuf::flush] before all rounds are done
+    pub const FLUSH_BEFORE_DONE: u32 = 1 << 2;
+    /// [DmaBuf::advance] after all rounds are done
+    pub const ADVANCE_AFTER_DONE: u32 = 1 << 3;
+    /// [DmaBuf::flush] once all rounds are done must succeed
+    pub const FLUSH: u32 = 1 << 4;
+    /// [DmaBuf::flush] twice
+    pub const FLUSH_TWICE: u32 = 1 << 5;
+    /// [DmaBuf::advance] after flushing
+    pub const ADVANCE_AFTER_FLUSH: u32 = 1 << 6;
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Use a [DmaBuf] over [source] out of order and outside of its team, returning the
+    /// [misuse] bits of the calls that were not reported with [WrapperError::InvalidState]
+    /// (0 if all of them were). The source is only read.
+    ///
+    /// # Safety:
+    /// * source must be longer than BUF_LEN, so that it takes more than one round
+    /// * source location must be correctly specified in [loc], which must not be L1
+    pub unsafe fn check_dma_misuse(&mut self, source: &[u8], loc: SourceLocation) -> Result<u32, WrapperError> {
+        if source.len() <= BUF_LEN || matches!(loc, SourceLocation::L1) {
+            return Err(WrapperError::InvalidLength);
+        }
+        let source_ptr = || SourcePtr::from_raw_parts(source.as_ptr() as *mut u8, source.len());
+        let mut failed = 0;
+        if !matches!(
+            <DmaBuf<CORES, BUF_LEN, DEPTH>>::new(source_ptr(), loc, source_ptr(), loc, &self.cluster_buffer, 1),
+            Err(WrapperError::InvalidState)
+        ) {
+            failed |= misuse::NEW_ON_FC;
+        }
+        let mut results = [0u32; CORES];
+        let mut data = CoreData::new(
+            // only ever read through this pointer
+            source.as_ptr() as *mut u8,
+            source.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            loc,
+        );
+        data.ctx = results.as_mut_ptr() as *const cty::c_void;
+        self.execute(Self::misuse_entry_point, data)?;
+        Ok(results.iter().fold(failed, |failed, core| failed | core))
+    }
+
+    extern "C" fn misuse_entry_point(data: &CoreData<BUF_LEN, DEPTH>) {
+        unsafe {
+            let failed = Self::misuse_dma_buf(data);
+            *(data.ctx as *mut u32).add(pi_core_id()) = failed;
+        }
+    }
+
+    /// Misuses that can only happen on the cluster, every core makes the same calls
+    ///
+    /// # Safety:
+    /// * must be called on every core of the cluster
+    unsafe fn misuse_dma_buf(data: &CoreData<BUF_LEN, DEPTH>) -> u32 {
+        let CoreData {
+            source,
+            len,
+            l1_alloc,
+            loc,
+            ..
+        } = *data;
+        let l1_alloc = &*l1_alloc;
+        let source_ptr = || SourcePtr::from_raw_parts(source, len);
+        let invalid = |res: Result<(), WrapperError>, bit: u32| match res {
+            Err(WrapperError::InvalidState) => 0,
+            _ => bit,
+        };
+        let mut failed = 0;
+
+        // a buffer for a single core, the team has CORES
+        if CORES != 1 {
+            let res = <DmaBuf<1, BUF_LEN, DEPTH>>::new(source_ptr(), loc, source_ptr(), loc, l1_alloc, 1);
+            failed |= invalid(res.map(|_| ()), misuse::NEW_TEAM_SIZE);
+        }
+
+        let buf = match <DmaBuf<CORES, BUF_LEN, DEPTH>>::new(source_ptr(), loc, source_ptr(), loc, l1_alloc, 1) {
+            Ok(buf) => buf,
+            Err(err) => {
+                data.raise(err);
+                return failed;
+            }
+        };
+        let mut buf: Pin<&mut DmaBuf<CORES, BUF_LEN, DEPTH>> = pin!(buf.read_only());
+        failed |= invalid(buf.as_mut().flush(), misuse::FLUSH_BEFORE_DONE);
+        while !buf.is_done() {
+            if let Err(err) = buf.as_mut().advance() {
+                data.raise(err);
+                return failed;
+            }
+        }
+        failed |= invalid(buf.as_mut().advance(), misuse::ADVANCE_AFTER_DONE);
+        if buf.as_mut().flush().is_err() {
+            failed |= misuse::FLUSH;
+        }
+        failed |= invalid(buf.as_mut().flush(), misuse::FLUSH_TWICE);
+        failed |= invalid(buf.as_mut().advance(), misuse::ADVANCE_AFTER_FLUSH);
+        failed
+    }
+}
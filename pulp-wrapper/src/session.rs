@@ -0,0 +1,137 @@
+use crate::*;
+
+/// Encryption / decryption of a stream that is not available all at once.
+///
+/// Each call to [update](Self::update) continues the keystream exactly where the previous
+/// one stopped, so splitting the stream in chunks of any length gives the same result as a
+/// single call to [PulpWrapper::run].
+///
+/// By default the stream ends with the keystream of the iv, see [segmented](Self::segmented)
+/// to continue past it.
+pub struct StreamSession<
+    'w,
+    C: KeyIvInit,
+    const CORES: usize,
+    const BUF_LEN: usize,
+    const DEPTH: usize = 3,
+> {
+    wrapper: &'w mut PulpWrapper<CORES, BUF_LEN, DEPTH>,
+    key: GenericArray<u8, C::KeySize>,
+    iv: GenericArray<u8, C::IvSize>,
+    // position in the stream of the next byte
+    offset: u64,
+    // iv of the keystream segments after the first one, if the stream continues past it
+    derive_iv: Option<DeriveIv<C>>,
+}
+
+impl<
+        'w,
+        C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock,
+        const CORES: usize,
+        const BUF_LEN: usize,
+        const DEPTH: usize,
+    > StreamSession<'w, C, CORES, BUF_LEN, DEPTH>
+{
+    pub(crate) fn new(
+        wrapper: &'w mut PulpWrapper<CORES, BUF_LEN, DEPTH>,
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+    ) -> Self {
+        Self {
+            wrapper,
+            key: key.clone(),
+            iv: iv.clone(),
+            offset: 0,
+            derive_iv: None,
+        }
+    }
+
+    /// Continue the stream past the end of the keystream, in segments as long as the keystream
+    /// of a single iv. Each segment restarts the keystream with the iv given by [derive_iv],
+    /// the first one uses the original iv.
+    pub fn segmented(mut self, derive_iv: DeriveIv<C>) -> Self {
+        self.derive_iv = Some(derive_iv);
+        self
+    }
+
+    /// Encrypt / decrypt the next [chunk] of the stream
+    ///
+    /// On error the session does not advance.
+    ///
+    /// # Safety:
+    /// * chunk location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn update(&mut self, chunk: &mut [u8], loc: SourceLocation) -> Result<RunReport, WrapperError> {
+        let Self {
+            wrapper,
+            key,
+            iv,
+            offset,
+            derive_iv,
+        } = self;
+        // check every piece before touching the data
+        for_each_segment::<C>(key, iv, *offset, *derive_iv, chunk.len(), |_, len, iv, offset| {
+            PulpWrapper::<CORES, BUF_LEN, DEPTH>::check::<C>(key, iv, len, loc, loc, offset).map(|_| ())
+        })?;
+        let mut report = RunReport::default();
+        for_each_segment::<C>(key, iv, *offset, *derive_iv, chunk.len(), |start, len, iv, offset| {
+            let piece = wrapper.run_at::<C>(&mut chunk[start..start + len], key, iv, loc, offset)?;
+            report.len += piece.len;
+            report.rounds += piece.rounds;
+            Ok(())
+        })?;
+        *offset += chunk.len() as u64;
+        Ok(report)
+    }
+
+    /// Continue the stream from byte [offset] instead of where it stopped
+    /// (e.g. to resume a stream, or to skip to a part of it)
+    pub fn seek(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// Position of the next byte of the stream, i.e. the number of bytes processed till now
+    /// unless the session was moved with [seek](Self::seek)
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// End the session, returning the position reached in the stream
+    pub fn finish(self) -> u64 {
+        self.offset
+    }
+}
+
+/// Split the [len] bytes of the stream starting at [offset] on keystream segments, calling [f]
+/// with the start of each piece in the chunk, its length, iv and offset in the keystream.
+fn for_each_segment<C: KeyIvInit + KeystreamBlock>(
+    key: &GenericArray<u8, C::KeySize>,
+    iv: &GenericArray<u8, C::IvSize>,
+    offset: u64,
+    derive_iv: Option<DeriveIv<C>>,
+    len: usize,
+    mut f: impl FnMut(usize, usize, &GenericArray<u8, C::IvSize>, u64) -> Result<(), WrapperError>,
+) -> Result<(), WrapperError> {
+    offset
+        .checked_add(len as u64)
+        .ok_or(WrapperError::KeystreamOverflow)?;
+    let (derive_iv, segment_len) = match (derive_iv, keystream_len::<C>(key, iv)) {
+        (Some(derive_iv), Some(segment_len)) if segment_len > 0 => (derive_iv, segment_len),
+        // a single segment, its end is checked by the wrapper
+        _ => return f(0, len, iv, offset),
+    };
+    let mut start = 0;
+    while start < len {
+        let pos = offset + start as u64;
+        let (segment, segment_offset) = (pos / segment_len, pos % segment_len);
+        let piece = core::cmp::min((len - start) as u64, segment_len - segment_offset) as usize;
+        let segment_iv = if segment == 0 {
+            iv.clone()
+        } else {
+            derive_iv(iv, segment).ok_or(WrapperError::KeystreamOverflow)?
+        };
+        f(start, piece, &segment_iv, segment_offset)?;
+        start += piece;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::*;
+use cipher::typenum::{IsLess, Le, NonZero, U256};
+use cipher::{InnerIvInit, IvSizeUser, KeyInit, StreamCipherCore, StreamCipherCoreWrapper};
+
+type Iv<C> = GenericArray<u8, <C as IvSizeUser>::IvSize>;
+
+/// Stream ciphers built on a block cipher (e.g. CTR modes), whose key schedule can be expanded
+/// once and reused with any iv, see [PulpWrapper::run_scheduled].
+pub trait KeySchedule: StreamCipher + StreamCipherSeek + KeystreamBlock + IvSizeUser + Clone {
+    /// The block cipher with its expanded key (e.g. `aes::Aes256`)
+    type Schedule: KeyInit + Clone;
+
+    /// Same as `Self::new(key, iv)`, without expanding the key again
+    fn from_schedule(schedule: &Self::Schedule, iv: &Iv<Self>) -> Self;
+}
+
+impl<T> KeySchedule for StreamCipherCoreWrapper<T>
+where
+    T: StreamCipherCore + InnerIvInit,
+    T::BlockSize: IsLess<U256>,
+    Le<T::BlockSize, U256>: NonZero,
+    T::Inner: KeyInit + Clone,
+    Self: StreamCipher + StreamCipherSeek + KeystreamBlock + IvSizeUser<IvSize = T::IvSize> + Clone,
+{
+    type Schedule = T::Inner;
+
+    fn from_schedule(schedule: &Self::Schedule, iv: &Iv<Self>) -> Self {
+        Self::from_core(T::inner_iv_init(schedule.clone(), iv))
+    }
+}
+
+/// Arguments of a task whose cores share [state], allocated in L1 as a whole
+struct SharedArgs<S, const BUF_LEN: usize, const DEPTH: usize> {
+    f: extern "C" fn(&CoreData<BUF_LEN, DEPTH>),
+    data: CoreData<BUF_LEN, DEPTH>,
+    state: S,
+}
+
+// Same as CoreData, the cores only ever read the state
+unsafe impl<S, const BUF_LEN: usize, const DEPTH: usize> Send for SharedArgs<S, BUF_LEN, DEPTH> {}
+unsafe impl<S, const BUF_LEN: usize, const DEPTH: usize> Sync for SharedArgs<S, BUF_LEN, DEPTH> {}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Same as [run](Self::run), but the cipher is built once by the FC and every core
+    /// clones it instead of expanding the key on its own.
+    ///
+    /// # Safety:
+    /// * same as [run](Self::run)
+    pub unsafe fn run_shared<C: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock + Clone>(
+        &mut self,
+        source: &mut [u8],
+        key: &GenericArray<u8, C::KeySize>,
+        iv: &GenericArray<u8, C::IvSize>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        self.run_cipher(source, C::new(key, iv), loc)
+    }
+
+    /// Same as [run_shared](Self::run_shared) with a key schedule expanded beforehand, so that
+    /// repeated runs with the same key skip the key expansion entirely.
+    ///
+    /// # Safety:
+    /// * same as [run](Self::run)
+    pub unsafe fn run_scheduled<C: KeySchedule>(
+        &mut self,
+        source: &mut [u8],
+        schedule: &C::Schedule,
+        iv: &Iv<C>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        self.run_cipher(source, C::from_schedule(schedule, iv), loc)
+    }
+
+    /// Encrypt / decrypt data in [source] with clones of [cipher]
+    ///
+    /// # Safety:
+    /// * same as [run](Self::run)
+    unsafe fn run_cipher<C: StreamCipher + StreamCipherSeek + KeystreamBlock + Clone>(
+        &mut self,
+        source: &mut [u8],
+        cipher: C,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        let report = Self::check_keystream(remaining_len(&cipher), source.len(), loc, loc, 0)?;
+        let data = CoreData::new(
+            source.as_mut_ptr(),
+            source.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            loc,
+        );
+        self.execute_shared(Self::shared_entry_point::<C>, data, &cipher)?;
+        Ok(report)
+    }
+
+    /// Same as [execute](Self::execute), with [state] available to every core through `data.state`.
+    ///
+    /// The state is copied to L1 together with the arguments of the task, so that the cores
+    /// always read it from L1. This needs `size_of::<S>()` bytes of L1 on top of what
+    /// [new](Self::new) reserved, and fails with [WrapperError::Alloc] if they are not free.
+    pub(crate) fn execute_shared<S: Clone>(
+        &mut self,
+        f: extern "C" fn(&CoreData<BUF_LEN, DEPTH>),
+        mut data: CoreData<BUF_LEN, DEPTH>,
+        state: &S,
+    ) -> Result<(), WrapperError> {
+        let mut errors = CoreErrors::<CORES>::new();
+        data.errors = errors.as_mut_ptr();
+        let args = SharedArgs {
+            f,
+            data,
+            state: state.clone(),
+        };
+        self.cluster
+            .execute_fn_parallel_with_stacks(Self::shared_state_entry_point::<S>, args, self.stacks)?;
+        errors.first()
+    }
+
+    extern "C" fn shared_state_entry_point<S>(args: &SharedArgs<S, BUF_LEN, DEPTH>) {
+        // the state is only known to be in L1 once the task is running
+        let mut data = args.data;
+        data.state = &args.state as *const S as *const cty::c_void;
+        (args.f)(&data)
+    }
+
+    extern "C" fn shared_entry_point<C: StreamCipher + StreamCipherSeek + KeystreamBlock + Clone>(
+        data: &CoreData<BUF_LEN, DEPTH>,
+    ) {
+        unsafe { Self::apply_keystream(data, data.shared_cipher::<C>()) }
+    }
+}
@@ -0,0 +1,40 @@
+use super::{Field, BLOCK_LEN};
+
+/// Element of GF(2^128) for GHASH, in the bit reflected representation of the GCM spec
+/// (the most significant bit of the first byte is the coefficient of x^0)
+#[derive(Clone, Copy)]
+pub(crate) struct GHash(u128);
+
+// x^128 + x^7 + x^2 + x + 1, reflected
+const R: u128 = 0xe1 << 120;
+
+impl Field for GHash {
+    const ZERO: Self = Self(0);
+    const ONE: Self = Self(1 << 127);
+
+    fn from_block(block: &[u8; BLOCK_LEN]) -> Self {
+        Self(u128::from_be_bytes(*block))
+    }
+
+    #[inline(always)]
+    fn add(&self, other: &Self) -> Self {
+        Self(self.0 ^ other.0)
+    }
+
+    /// Constant time shift-and-add multiplication (NIST SP 800-38D, algorithm 1)
+    fn mul(&self, other: &Self) -> Self {
+        let (x, mut v) = (self.0, other.0);
+        let mut z = 0;
+        for i in (0..128).rev() {
+            z ^= v & 0u128.wrapping_sub((x >> i) & 1);
+            v = (v >> 1) ^ (R & 0u128.wrapping_sub(v & 1));
+        }
+        Self(z)
+    }
+}
+
+impl GHash {
+    pub fn to_bytes(self) -> [u8; BLOCK_LEN] {
+        self.0.to_be_bytes()
+    }
+}
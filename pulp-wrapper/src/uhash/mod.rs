@@ -0,0 +1,122 @@
+//! Polynomial universal hashes (Poly1305, GHASH) split so that they can be evaluated in parallel.
+//!
+//! The hash of a message of `n` blocks is `sum(m_i * h^(n - i + 1))`: each core
+//! evaluates its own run of blocks with Horner's rule and then shifts the result in
+//! place by multiplying it by the right power of `h`, so that partial results from
+//! different cores can simply be added together.
+
+mod ghash;
+mod poly1305;
+pub(crate) use ghash::GHash;
+pub(crate) use poly1305::Poly1305;
+
+pub(crate) const BLOCK_LEN: usize = 16;
+
+/// Field the polynomial is evaluated in
+pub(crate) trait Field: Copy {
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_block(block: &[u8; BLOCK_LEN]) -> Self;
+
+    fn add(&self, other: &Self) -> Self;
+
+    fn mul(&self, other: &Self) -> Self;
+}
+
+/// Evaluate [data] with Horner's rule, starting from zero.
+/// A trailing partial block is zero padded, as in the AEAD constructions.
+pub(crate) fn horner<F: Field>(data: &[u8], h: &F) -> F {
+    let mut acc = F::ZERO;
+    let mut blocks = data.chunks_exact(BLOCK_LEN);
+    for block in &mut blocks {
+        acc = acc.add(&F::from_block(block.try_into().unwrap())).mul(h);
+    }
+    let rem = blocks.remainder();
+    if !rem.is_empty() {
+        let mut block = [0; BLOCK_LEN];
+        block[..rem.len()].copy_from_slice(rem);
+        acc = acc.add(&F::from_block(&block)).mul(h);
+    }
+    acc
+}
+
+/// `h^(2^i)` for every bit of a block count, to shift partial results by any number of blocks
+pub(crate) struct Powers<F>([F; usize::BITS as usize]);
+
+impl<F: Field> Powers<F> {
+    pub fn new(h: F) -> Self {
+        let mut powers = [h; usize::BITS as usize];
+        for i in 1..powers.len() {
+            powers[i] = powers[i - 1].mul(&powers[i - 1]);
+        }
+        Self(powers)
+    }
+
+    pub fn h(&self) -> &F {
+        &self.0[0]
+    }
+
+    /// `h^exp`
+    pub fn pow(&self, mut exp: usize) -> F {
+        let mut res = F::ONE;
+        let mut i = 0;
+        while exp != 0 {
+            if exp & 1 != 0 {
+                res = res.mul(&self.0[i]);
+            }
+            exp >>= 1;
+            i += 1;
+        }
+        res
+    }
+}
+
+/// State shared by all cores to hash the data streamed through the cluster,
+/// laid out as `aad || data || lengths`, each part padded to the block size.
+pub(crate) struct HashCtx<F> {
+    powers: Powers<F>,
+    // blocks of data (the last one might be padded)
+    blocks: usize,
+    // one for each core
+    partials: *mut F,
+}
+
+impl<F: Field> HashCtx<F> {
+    pub fn new(h: F, len: usize, partials: *mut F) -> Self {
+        Self {
+            powers: Powers::new(h),
+            blocks: (len + BLOCK_LEN - 1) / BLOCK_LEN,
+            partials,
+        }
+    }
+
+    /// Contribution to the hash of [chunk], found at offset [pos] in the data
+    pub fn partial(&self, chunk: &[u8], pos: usize) -> F {
+        if chunk.is_empty() {
+            return F::ZERO;
+        }
+        let end_block = (pos + chunk.len() + BLOCK_LEN - 1) / BLOCK_LEN;
+        // data blocks after this chunk + the lengths block
+        let shift = self.blocks - end_block + 1;
+        horner(chunk, self.powers.h()).mul(&self.powers.pow(shift))
+    }
+
+    /// Store the result of the current core
+    ///
+    /// # Safety:
+    /// * must be called at most once per core
+    pub unsafe fn set_partial(&self, core_id: usize, partial: F) {
+        *self.partials.add(core_id) = partial;
+    }
+
+    /// Combine the partial results of each core with the aad and the lengths block
+    pub fn finish(&self, partials: &[F], aad: &[u8], lengths: &[u8; BLOCK_LEN]) -> F {
+        let h = self.powers.h();
+        let mut acc = horner(aad, h).mul(&self.powers.pow(self.blocks + 1));
+        for partial in partials {
+            acc = acc.add(partial);
+        }
+        acc.add(&horner(lengths, h))
+    }
+}
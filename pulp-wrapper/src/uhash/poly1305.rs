@@ -0,0 +1,135 @@
+use super::Field;
+
+/// Element of GF(2^130 - 5) for Poly1305, as 5 limbs of 26 bits (not necessarily fully reduced)
+#[derive(Clone, Copy)]
+pub(crate) struct Poly1305([u32; 5]);
+
+const MASK: u32 = 0x3ff_ffff;
+
+#[inline(always)]
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl Field for Poly1305 {
+    const ZERO: Self = Self([0; 5]);
+    const ONE: Self = Self([1, 0, 0, 0, 0]);
+
+    /// Load a 16 bytes block, setting the bit past its end
+    fn from_block(block: &[u8; 16]) -> Self {
+        Self([
+            le32(&block[0..]) & MASK,
+            (le32(&block[3..]) >> 2) & MASK,
+            (le32(&block[6..]) >> 4) & MASK,
+            (le32(&block[9..]) >> 6) & MASK,
+            (le32(&block[12..]) >> 8) | (1 << 24),
+        ])
+    }
+
+    #[inline(always)]
+    fn add(&self, other: &Self) -> Self {
+        let (a, b) = (&self.0, &other.0);
+        Self::carry([a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3], a[4] + b[4]])
+    }
+
+    #[inline(always)]
+    fn mul(&self, other: &Self) -> Self {
+        let h = self.0.map(|x| x as u64);
+        let r = other.0.map(|x| x as u64);
+        let s = [0, r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+
+        let mut d = [
+            h[0] * r[0] + h[1] * s[4] + h[2] * s[3] + h[3] * s[2] + h[4] * s[1],
+            h[0] * r[1] + h[1] * r[0] + h[2] * s[4] + h[3] * s[3] + h[4] * s[2],
+            h[0] * r[2] + h[1] * r[1] + h[2] * r[0] + h[3] * s[4] + h[4] * s[3],
+            h[0] * r[3] + h[1] * r[2] + h[2] * r[1] + h[3] * r[0] + h[4] * s[4],
+            h[0] * r[4] + h[1] * r[3] + h[2] * r[2] + h[3] * r[1] + h[4] * r[0],
+        ];
+        for i in 0..4 {
+            d[i + 1] += d[i] >> 26;
+            d[i] &= MASK as u64;
+        }
+        let c = d[4] >> 26;
+        d[4] &= MASK as u64;
+        d[0] += c * 5;
+        d[1] += d[0] >> 26;
+        d[0] &= MASK as u64;
+        Self(d.map(|x| x as u32))
+    }
+}
+
+impl Poly1305 {
+    /// Load and clamp `r` from the first half of the one-time key
+    pub fn from_key(r: &[u8; 16]) -> Self {
+        Self([
+            le32(&r[0..]) & 0x3ff_ffff,
+            (le32(&r[3..]) >> 2) & 0x3ff_ff03,
+            (le32(&r[6..]) >> 4) & 0x3ff_c0ff,
+            (le32(&r[9..]) >> 6) & 0x3f0_3fff,
+            (le32(&r[12..]) >> 8) & 0x00f_ffff,
+        ])
+    }
+
+    /// Load the last block of a standalone MAC, where a partial block ends with a 1 byte
+    /// instead of the bit past the end of the block
+    pub fn from_last_block(block: &[u8]) -> Self {
+        if let Ok(block) = block.try_into() {
+            return Self::from_block(block);
+        }
+        let mut padded = [0; 16];
+        padded[..block.len()].copy_from_slice(block);
+        padded[block.len()] = 1;
+        let mut last = Self::from_block(&padded);
+        last.0[4] &= !(1 << 24);
+        last
+    }
+
+    #[inline(always)]
+    fn carry(mut h: [u32; 5]) -> Self {
+        let mut c;
+        c = h[0] >> 26; h[0] &= MASK; h[1] += c;
+        c = h[1] >> 26; h[1] &= MASK; h[2] += c;
+        c = h[2] >> 26; h[2] &= MASK; h[3] += c;
+        c = h[3] >> 26; h[3] &= MASK; h[4] += c;
+        c = h[4] >> 26; h[4] &= MASK; h[0] += c * 5;
+        c = h[0] >> 26; h[0] &= MASK; h[1] += c;
+        Self(h)
+    }
+
+    /// Fully reduce, add `s` and serialize the tag
+    pub fn finalize(&self, s: &[u8; 16]) -> [u8; 16] {
+        let mut h = Self::carry(self.0).0;
+        let mut c;
+        c = h[1] >> 26; h[1] &= MASK; h[2] += c;
+        c = h[2] >> 26; h[2] &= MASK; h[3] += c;
+        c = h[3] >> 26; h[3] &= MASK; h[4] += c;
+        c = h[4] >> 26; h[4] &= MASK; h[0] += c * 5;
+        c = h[0] >> 26; h[0] &= MASK; h[1] += c;
+
+        // compute h - p and select it if it's not negative
+        let mut g = [0u32; 5];
+        g[0] = h[0] + 5; c = g[0] >> 26; g[0] &= MASK;
+        g[1] = h[1] + c; c = g[1] >> 26; g[1] &= MASK;
+        g[2] = h[2] + c; c = g[2] >> 26; g[2] &= MASK;
+        g[3] = h[3] + c; c = g[3] >> 26; g[3] &= MASK;
+        g[4] = (h[4] + c).wrapping_sub(1 << 26);
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        let h = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; 16];
+        let mut f = 0u64;
+        for i in 0..4 {
+            f = h[i] as u64 + le32(&s[i * 4..]) as u64 + (f >> 32);
+            tag[i * 4..i * 4 + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        tag
+    }
+}
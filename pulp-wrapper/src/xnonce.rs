@@ -0,0 +1,69 @@
+use crate::*;
+use cipher::consts::U16;
+use cipher::{IvSizeUser, KeySizeUser};
+
+type Key<C> = GenericArray<u8, <C as KeySizeUser>::KeySize>;
+type Iv<C> = GenericArray<u8, <C as IvSizeUser>::IvSize>;
+
+/// Stream ciphers with an extended nonce, which are the cipher [Inner](Self::Inner)
+/// keyed with a subkey derived from the key and the first 16 bytes of the nonce.
+///
+/// The subkey only depends on the key and the nonce, so the FC derives it once
+/// and the cores run [Inner](Self::Inner) directly, see [PulpWrapper::run_extended].
+pub trait ExtendedNonce: KeyIvInit {
+    type Inner: StreamCipher + StreamCipherSeek + KeyIvInit + KeystreamBlock;
+
+    /// Key and iv of [Inner](Self::Inner) giving the same keystream as `Self::new(key, iv)`
+    fn derive(key: &Key<Self>, iv: &Iv<Self>) -> (Key<Self::Inner>, Iv<Self::Inner>);
+}
+
+/// XChaCha20: HChaCha20 subkey, then ChaCha20 with the last 8 bytes of the nonce
+/// after 4 zero bytes (draft-irtf-cfrg-xchacha)
+impl ExtendedNonce for chacha20::XChaCha20 {
+    type Inner = chacha20::ChaCha20;
+
+    fn derive(key: &Key<Self>, iv: &Iv<Self>) -> (Key<Self::Inner>, Iv<Self::Inner>) {
+        let subkey = chacha20::hchacha::<cipher::consts::U10>(key, GenericArray::<u8, U16>::from_slice(&iv[..16]));
+        let mut inner_iv = Iv::<Self::Inner>::default();
+        inner_iv[4..].copy_from_slice(&iv[16..]);
+        (subkey, inner_iv)
+    }
+}
+
+/// XSalsa20: HSalsa20 subkey, then Salsa20 with the last 8 bytes of the nonce
+impl ExtendedNonce for salsa20::XSalsa20 {
+    type Inner = salsa20::Salsa20;
+
+    fn derive(key: &Key<Self>, iv: &Iv<Self>) -> (Key<Self::Inner>, Iv<Self::Inner>) {
+        let subkey = salsa20::hsalsa::<cipher::consts::U10>(key, GenericArray::<u8, U16>::from_slice(&iv[..16]));
+        (subkey, *Iv::<Self::Inner>::from_slice(&iv[16..]))
+    }
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Same as [run](Self::run) for a cipher with an extended nonce, deriving the subkey
+    /// on the FC instead of on every core.
+    ///
+    /// # Safety:
+    /// * same as [run](Self::run)
+    pub unsafe fn run_extended<X: ExtendedNonce>(
+        &mut self,
+        source: &mut [u8],
+        key: &Key<X>,
+        iv: &Iv<X>,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError> {
+        let (key, iv) = X::derive(key, iv);
+        self.run::<X::Inner>(source, &key, &iv, loc)
+    }
+
+    /// Same as [session](Self::session) for a cipher with an extended nonce
+    pub fn session_extended<X: ExtendedNonce>(
+        &mut self,
+        key: &Key<X>,
+        iv: &Iv<X>,
+    ) -> StreamSession<'_, X::Inner, CORES, BUF_LEN, DEPTH> {
+        let (key, iv) = X::derive(key, iv);
+        StreamSession::new(self, &key, &iv)
+    }
+}
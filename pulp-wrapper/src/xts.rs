@@ -0,0 +1,150 @@
+use crate::*;
+use cipher::consts::U16;
+use cipher::{Block, BlockCipher, BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
+
+const BLOCK_LEN: usize = 16;
+
+/// Sectors of the data and their numbering, shared by the cores
+struct XtsCtx {
+    first_sector: u64,
+    sector_size: usize,
+}
+
+impl<const CORES: usize, const BUF_LEN: usize, const DEPTH: usize> PulpWrapper<CORES, BUF_LEN, DEPTH> {
+    /// Encrypt (or decrypt if [DECRYPT]) data in [source] with XTS (IEEE 1619), one sector of
+    /// [sector_size] bytes after the other starting from sector number [first_sector].
+    ///
+    /// Each core processes whole sectors, and a partial final sector is handled with ciphertext
+    /// stealing. [sector_size] must divide `BUF_LEN`, and every sector must be at least 16 bytes long.
+    ///
+    /// # Safety:
+    /// * source location must be correctly specified in [loc]
+    /// * if present, ram device pointer must be valid to read for the whole duration
+    pub unsafe fn run_xts<B, const DECRYPT: bool>(
+        &mut self,
+        source: &mut [u8],
+        key1: &GenericArray<u8, B::KeySize>,
+        key2: &GenericArray<u8, B::KeySize>,
+        first_sector: u64,
+        sector_size: usize,
+        loc: SourceLocation,
+    ) -> Result<RunReport, WrapperError>
+    where
+        B: BlockEncrypt + BlockDecrypt + BlockCipher + BlockSizeUser<BlockSize = U16> + KeyInit + Clone,
+    {
+        // sectors must not straddle rounds, and ciphertext stealing needs at least a full block
+        if sector_size < BLOCK_LEN || BUF_LEN % sector_size != 0 {
+            return Err(WrapperError::InvalidLength);
+        }
+        let last_sector = source.len() % sector_size;
+        if last_sector != 0 && last_sector < BLOCK_LEN {
+            return Err(WrapperError::InvalidLength);
+        }
+        // the number of the last sector must fit in 64 bits
+        let sectors = (source.len() + sector_size - 1) / sector_size;
+        first_sector
+            .checked_add((sectors as u64).saturating_sub(1))
+            .ok_or(WrapperError::InvalidLength)?;
+        let report = Self::check_keystream(None, source.len(), loc, loc, 0)?;
+        let ctx = XtsCtx {
+            first_sector,
+            sector_size,
+        };
+        let mut data = CoreData::new(
+            source.as_mut_ptr(),
+            source.len(),
+            &self.cluster_buffer,
+            core::ptr::null(),
+            core::ptr::null(),
+            loc,
+        );
+        data.ctx = &ctx as *const XtsCtx as *const cty::c_void;
+        self.execute_shared(Self::xts_entry_point::<B, DECRYPT>, data, &(B::new(key1), B::new(key2)))?;
+        Ok(report)
+    }
+
+    extern "C" fn xts_entry_point<B, const DECRYPT: bool>(data: &CoreData<BUF_LEN, DEPTH>)
+    where
+        B: BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16> + Clone,
+    {
+        unsafe {
+            let (cipher, tweak_cipher) = data.shared_cipher::<(B, B)>();
+            let ctx = &*(data.ctx as *const XtsCtx);
+            let partition = Partition::new(ctx.sector_size, 0);
+            Self::for_each_chunk(data, partition, |chunk, pos| {
+                // sectors are always processed in place
+                let sectors = chunk.into_out().chunks_mut(ctx.sector_size);
+                let first = ctx.first_sector + (pos / ctx.sector_size) as u64;
+                for (i, sector) in sectors.enumerate() {
+                    let mut tweak = Block::<B>::from(((first + i as u64) as u128).to_le_bytes());
+                    tweak_cipher.encrypt_block(&mut tweak);
+                    xts_sector::<B, DECRYPT>(&cipher, u128::from_le_bytes(tweak.into()), sector);
+                }
+            });
+        }
+    }
+}
+
+/// Encrypt / decrypt a sector of at least one block, stealing ciphertext from the last full block
+/// if it ends with a partial one
+fn xts_sector<B, const DECRYPT: bool>(cipher: &B, mut tweak: u128, sector: &mut [u8])
+where
+    B: BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16>,
+{
+    let stolen = sector.len() % BLOCK_LEN;
+    // the last full block is processed together with the partial one
+    let full = sector.len() / BLOCK_LEN - (stolen != 0) as usize;
+    let (head, rest) = sector.split_at_mut(full * BLOCK_LEN);
+
+    // xor the tweaks in and out around a single call, so that the cipher can process
+    // several blocks at once
+    let blocks = InOutBuf::from(head).into_chunks::<U16>().0.into_out();
+    let mut t = tweak;
+    for block in blocks.iter_mut() {
+        xor_tweak(block, t);
+        t = mul_alpha(t);
+    }
+    if DECRYPT {
+        cipher.decrypt_blocks(blocks);
+    } else {
+        cipher.encrypt_blocks(blocks);
+    }
+    for block in blocks.iter_mut() {
+        xor_tweak(block, tweak);
+        tweak = mul_alpha(tweak);
+    }
+    if stolen == 0 {
+        return;
+    }
+
+    let next = mul_alpha(tweak);
+    let (first, second) = if DECRYPT { (next, tweak) } else { (tweak, next) };
+    let (block, tail) = rest.split_at_mut(BLOCK_LEN);
+    let block = GenericArray::from_mut_slice(block);
+    xts_block::<B, DECRYPT>(cipher, block, first);
+    block[..stolen].swap_with_slice(tail);
+    xts_block::<B, DECRYPT>(cipher, block, second);
+}
+
+fn xts_block<B, const DECRYPT: bool>(cipher: &B, block: &mut Block<B>, tweak: u128)
+where
+    B: BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16>,
+{
+    xor_tweak(block, tweak);
+    if DECRYPT {
+        cipher.decrypt_block(block);
+    } else {
+        cipher.encrypt_block(block);
+    }
+    xor_tweak(block, tweak);
+}
+
+fn xor_tweak(block: &mut GenericArray<u8, U16>, tweak: u128) {
+    let value = u128::from_le_bytes((*block).into()) ^ tweak;
+    block.copy_from_slice(&value.to_le_bytes());
+}
+
+/// Tweak of the next block: multiplication by x in GF(2^128), little-endian
+fn mul_alpha(tweak: u128) -> u128 {
+    (tweak << 1) ^ ((tweak >> 127) * 0x87)
+}